@@ -1,13 +1,11 @@
 extern crate bevy_compute;
 
-use std::num::NonZeroU32;
-
 use bevy::{
 	prelude::*,
 	render::render_resource::{StorageTextureAccess, TextureFormat},
 };
 use bevy_compute::{
-	BevyComputePlugin, Binding, ComputeAction, ComputeStep, ComputeTask, DoubleBufferedSprite, ShaderBufferSet,
+	BevyComputePlugin, Binding, ComputeSequenceId, ComputeTask, DispatchSize, DoubleBufferedSprite, Hz, ShaderBufferSet,
 	StartComputeEvent,
 };
 
@@ -16,7 +14,6 @@ const SHADER_ASSET_PATH: &str = "shaders/game_of_life.wgsl";
 
 const DISPLAY_FACTOR: u32 = 4;
 const SIZE: (u32, u32) = (1280 / DISPLAY_FACTOR, 720 / DISPLAY_FACTOR);
-const WORKGROUP_SIZE: u32 = 8;
 
 fn main() {
 	App::new()
@@ -64,43 +61,21 @@ fn setup(
 	));
 	commands.spawn(Camera2d);
 
-	start_compute_events.send(StartComputeEvent {
-		tasks: vec![
-			ComputeTask {
-				label: Some("Init".to_owned()),
-				iterations: NonZeroU32::new(1),
-				steps: vec![
-					ComputeStep {
-						max_frequency: None,
-						action: ComputeAction::RunShader {
-							shader: SHADER_ASSET_PATH.to_owned(),
-							entry_point: "init".to_owned(),
-							x_workgroup_count: SIZE.0 / WORKGROUP_SIZE,
-							y_workgroup_count: SIZE.1 / WORKGROUP_SIZE,
-							z_workgroup_count: 1,
-						},
-					},
-					ComputeStep { max_frequency: None, action: ComputeAction::SwapBuffers { buffer: image } },
-				],
-			},
-			ComputeTask {
-				label: Some("Update".to_owned()),
-				iterations: None,
-				steps: vec![
-					ComputeStep {
-						max_frequency: NonZeroU32::new(10),
-						action: ComputeAction::RunShader {
-							shader: SHADER_ASSET_PATH.to_owned(),
-							entry_point: "update".to_owned(),
-							x_workgroup_count: SIZE.0 / WORKGROUP_SIZE,
-							y_workgroup_count: SIZE.1 / WORKGROUP_SIZE,
-							z_workgroup_count: 1,
-						},
-					},
-					ComputeStep { max_frequency: NonZeroU32::new(10), action: ComputeAction::SwapBuffers { buffer: image } },
-				],
-			},
-		],
-		iteration_buffer: None,
-	});
+	start_compute_events.write(
+		StartComputeEvent::new(ComputeSequenceId(0))
+			.task(
+				ComputeTask::new("Init")
+					.iterations(1)
+					.run(SHADER_ASSET_PATH, "init", DispatchSize::PerTexel(image))
+					.swap(image),
+			)
+			.task(
+				ComputeTask::new("Update")
+					.forever()
+					.run(SHADER_ASSET_PATH, "update", DispatchSize::PerTexel(image))
+					.every(10.hz())
+					.swap(image)
+					.every(10.hz()),
+			),
+	);
 }
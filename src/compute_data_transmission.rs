@@ -1,15 +1,20 @@
-use std::sync::mpsc::{Receiver, SyncSender};
+use std::sync::mpsc::{Receiver, Sender};
 
-use super::{ComputeTaskDoneEvent, CopyBufferEvent};
+use super::{ComputeErrorEvent, ComputeProgressEvent, ComputeSequenceDoneEvent, ComputeTaskDoneEvent, CopyBufferEvent};
 use crate::shader_buffer_set::ShaderBufferHandle;
 
 pub struct ComputeDataTransmission {
-	pub sender: SyncSender<ComputeMessage>,
+	pub sender: Sender<ComputeMessage>,
 	pub receiver: Receiver<ComputeMessage>,
 }
 
 pub enum ComputeMessage {
 	CopyBuffer(CopyBufferEvent),
 	GroupDone(ComputeTaskDoneEvent),
+	// The sequence's generation is sent along with the event, so the main world can tell if it's about a sequence that
+	// has since been replaced.
+	SequenceDone(ComputeSequenceDoneEvent, u64),
 	SwapBuffers(ShaderBufferHandle),
+	Error(ComputeErrorEvent),
+	Progress(ComputeProgressEvent),
 }
@@ -1,19 +1,92 @@
 use bevy::prelude::*;
+use bevy_platform::collections::hash_map::HashMap;
 
-use super::{compute_data_transmission::ComputeDataTransmission, compute_sequence::ComputeSequence, StartComputeEvent};
+use super::{
+	compute_data_transmission::ComputeDataTransmission,
+	compute_sequence::{flatten_elements, ComputeControl, ComputeSequence, ComputeSequences},
+	shader_buffer_set::ShaderBufferSet,
+	ComputeErrorEvent, ComputeSequenceDoneEvent, ComputeSequenceOutcome, PauseComputeEvent, ResumeComputeEvent,
+	StartComputeEvent, StopComputeEvent,
+};
 
 pub fn compute_main_setup(
-	mut commands: Commands, mut start_events: EventReader<StartComputeEvent>,
-	transmission: NonSend<ComputeDataTransmission>,
+	mut sequences: ResMut<ComputeSequences>, mut start_events: EventReader<StartComputeEvent>,
+	transmission: NonSend<ComputeDataTransmission>, buffers: Res<ShaderBufferSet>,
+	mut error_events: EventWriter<ComputeErrorEvent>, mut done_events: EventWriter<ComputeSequenceDoneEvent>,
+	mut next_generation: Local<u64>,
 ) {
-	if let Some(event) = start_events.read().next() {
-		commands.insert_resource(ComputeSequence {
-			sender: transmission.sender.clone(),
-			tasks: event.tasks.clone(),
-			iteration_buffer: event.iteration_buffer,
-		});
-		if let Some(_) = start_events.read().next() {
-			panic!("Attempted to start multiple compute sequences at once");
+	// If there's already a sequence running with this id, then it's simply replaced.
+	for event in start_events.read() {
+		let mut tasks = Vec::new();
+		let mut loops = Vec::new();
+		flatten_elements(&event.tasks, &mut tasks, &mut loops);
+
+		// A sequence that uses its buffers wrong is turned away before it gets to the render world, leaving any sequence
+		// that's already running with this id alone.
+		let errors = buffers.sequence_errors(&tasks, &loops);
+		if !errors.is_empty() {
+			for error in errors {
+				error!("Compute sequence {} is invalid: {}", event.id, error);
+				let (shader, entry_point) =
+					error.step.map(|step| tasks[error.task].steps[step].action.shader_and_entry_point()).unwrap_or_default();
+				error_events.write(ComputeErrorEvent {
+					sequence: event.id,
+					task: error.task,
+					step: error.step,
+					shader,
+					entry_point,
+					error: error.error,
+				});
+			}
+			done_events.write(ComputeSequenceDoneEvent { sequence: event.id, outcome: ComputeSequenceOutcome::Rejected });
+			continue;
+		}
+
+		*next_generation += 1;
+		sequences.0.insert(
+			event.id,
+			ComputeSequence {
+				sender: transmission.sender.clone(),
+				status: default(),
+				tasks,
+				loops,
+				iteration_buffer: event.iteration_buffer,
+				wait_for_pipelines: event.wait_for_pipelines,
+				progress_interval: event.progress_interval,
+				pipeline_timeout: event.pipeline_timeout,
+				control: ComputeControl::Run,
+				generation: *next_generation,
+			},
+		);
+	}
+}
+
+pub fn compute_main_control(
+	mut sequences: ResMut<ComputeSequences>, mut stop_events: EventReader<StopComputeEvent>,
+	mut pause_events: EventReader<PauseComputeEvent>, mut resume_events: EventReader<ResumeComputeEvent>,
+) {
+	// The events are collected in order of priority. A stop can't be undone, so it overrides anything else sent the
+	// same frame. Otherwise, a resume overrides a pause, so that pausing and resuming in the same frame leaves the
+	// sequence running.
+	let mut requests = HashMap::new();
+	for PauseComputeEvent(id) in pause_events.read() {
+		requests.insert(*id, ComputeControl::Pause);
+	}
+	for ResumeComputeEvent(id) in resume_events.read() {
+		requests.insert(*id, ComputeControl::Run);
+	}
+	for StopComputeEvent(id) in stop_events.read() {
+		requests.insert(*id, ComputeControl::Stop);
+	}
+
+	for (id, control) in requests {
+		// Only touch the resource if something actually changed, so it doesn't get re-extracted every frame.
+		let Some(sequence) = sequences.bypass_change_detection().0.get_mut(&id) else {
+			continue;
+		};
+		if sequence.control != ComputeControl::Stop && sequence.control != control {
+			sequence.control = control;
+			sequences.set_changed();
 		}
 	}
 }
@@ -4,154 +4,814 @@ use std::{
 };
 
 use bevy::{
+	asset::LoadState,
 	ecs::system::SystemState,
 	prelude::*,
 	render::{
+		render_asset::RenderAssets,
 		render_graph::{Node, NodeRunError, RenderGraphContext},
 		render_resource::{
-			CachedComputePipelineId, CachedPipelineState, ComputePassDescriptor, ComputePipelineDescriptor, PipelineCache,
+			BindGroup, Buffer, BufferDescriptor, BufferInitDescriptor, BufferUsages, CachedComputePipelineId,
+			CachedPipelineState, ComputePass, ComputePassDescriptor, ComputePipeline, ComputePipelineDescriptor, Maintain,
+			PipelineCache, PushConstantRange, Shader, ShaderDefVal, ShaderStages,
 		},
 		renderer::{RenderContext, RenderDevice, RenderQueue},
+		settings::WgpuFeatures,
+		texture::GpuImage,
 	},
 };
+use bevy_platform::collections::hash_map::HashMap;
 
 use super::{
 	compute_bind_groups::ComputeBindGroups,
 	compute_data_transmission::ComputeMessage,
-	compute_sequence::{ComputeAction, ComputeSequence, ComputeStep},
-	ComputeTaskDoneEvent, CopyBufferEvent,
+	compute_sequence::{
+		ComputeAction, ComputeControl, ComputeLoopKind, ComputeSchedule, ComputeSequence, ComputeSequenceId,
+		ComputeSequences, ComputeStep, DispatchSize, ShaderVariant,
+	},
+	shader_reflection::ShaderReflections,
+	step_graph::step_batches,
+	ComputeErrorEvent, ComputeProgressEvent, ComputeSequenceDoneEvent, ComputeSequenceOutcome, ComputeSequenceStatus,
+	ComputeTaskDoneEvent, ComputeTaskEndReason, CopyBufferEvent,
+};
+use crate::shader_buffer_set::{
+	BoundId, ShaderBufferHandle, ShaderBufferRenderSet, ShaderBufferSet, INDIRECT_ARGS_SIZE,
 };
-use crate::shader_buffer_set::{ShaderBufferRenderSet, ShaderBufferSet};
+
+type ComputeNodeUpdateParams = (
+	ResMut<'static, ShaderBufferSet>,
+	ResMut<'static, ShaderBufferRenderSet>,
+	Res<'static, RenderDevice>,
+	Res<'static, RenderQueue>,
+	Res<'static, ComputeSequences>,
+	ResMut<'static, PipelineCache>,
+	Res<'static, AssetServer>,
+	Res<'static, ShaderReflections>,
+	Res<'static, RenderAssets<GpuImage>>,
+);
+
+// How often the iterations per second are worked out, averaging over the iterations run since the last time.
+const RATE_INTERVAL: Duration = Duration::from_secs(1);
+
+// The most bytes of a fill step's value that are kept around to copy from. Bigger buffers are filled with a copy for
+// each chunk this size, since wgpu won't copy from one part of a buffer to another part of the same buffer.
+const FILL_CHUNK_SIZE: u64 = 1 << 16;
 
 pub struct ComputeNode {
+	id: ComputeSequenceId,
 	sequence: ComputeSequence,
 	current_task: usize,
 	current_pipelines_loaded: bool,
 	step_states: Vec<ComputeStepState>,
+	// The order to run the current task's steps in, as batches of steps that don't depend on each other.
+	step_batches: Vec<Vec<usize>>,
 	iterations: u32,
+	iterations_this_frame: u32,
+	// When more than one iteration runs in a frame, this holds the iteration numbers for all but the first of them,
+	// to be copied into the iteration buffer between iterations.
+	iteration_values: Option<Buffer>,
 	group_start_time: Instant,
+	pipelines_queued_at: Instant,
+	paused_at: Option<Instant>,
+	// The buffers copied back at the end of every iteration, for the task's condition and the conditions of any loops
+	// that end with the task.
+	condition_buffers: Vec<ShaderBufferHandle>,
+	// The latest data copied back from each condition buffer during the current task.
+	condition_data: HashMap<ShaderBufferHandle, Vec<u8>>,
+	// Whether the task's condition has been satisfied, in case it has to wait for its last copies to come back.
+	condition_met: bool,
+	// The staging buffers this frame's copies back to the CPU go into, along with the buffers they're copies of.
+	staged_copies: Vec<(ShaderBufferHandle, Buffer)>,
+	// Bind groups made for double buffers swapped part way through a frame, kept for the rest of the task, by what's
+	// bound in them.
+	swapped_bind_groups: HashMap<Vec<BoundId>, Vec<BindGroup>>,
+	// The bind groups to use this frame after each combination of swaps, sorted, that happens during it.
+	frame_bind_groups: HashMap<Vec<ShaderBufferHandle>, Vec<BindGroup>>,
+	// How many times each of the sequence's loops has gone round.
+	loop_iterations: Vec<u32>,
+	// The pipelines for every step in the sequence, shared between steps that would make identical ones.
+	pipelines: HashMap<PipelineKey, QueuedPipeline>,
+	pipelines_queued: bool,
+	all_pipelines_ready: bool,
+	// The iterations run since the sequence started, across all tasks, for working out the iteration rate.
+	total_iterations: u64,
+	// When the iteration rate was last worked out, and the total iterations at the time.
+	rate_measured_at: (Instant, u64),
+	iterations_per_second: f32,
+	progress_sent_at: Instant,
 }
 
 struct ComputeStepState {
 	step: ComputeStep,
 	id: Option<CachedComputePipelineId>,
+	// The shader this step runs, along with everything it's compiled with, if it runs one.
+	variant: Option<ShaderVariant>,
+	// The handle of the shader file, so it can tell if it failed to load.
+	shader: Option<Handle<Shader>>,
+	// The pipeline that's used to run the shader. This is kept separately from the pipeline cache, so that it can
+	// keep being used while a new version is compiled.
+	pipeline: Option<ComputePipeline>,
+	// Whether the latest attempt to compile a new version failed, so it's only reported once.
+	reload_failed: bool,
 	last_run_time: Instant,
-	run_this_time: bool,
-	copy_buffer_ready: bool,
+	// Whether the step runs on each of this frame's iterations.
+	runs: Vec<bool>,
+	// Indirect dispatches copy their workgroup counts in here right before dispatching, so the buffer they came from
+	// is free to be bound as writable in the same pass.
+	indirect_buffer: Option<Buffer>,
+	// Fill steps copy their value in from here, a chunk at a time, since there's no command to fill a buffer with
+	// anything but zeroes.
+	fill_buffer: Option<Buffer>,
+	// The workgroup size declared by the shader, for steps that need it to work out their workgroup counts.
+	workgroup_size: Option<[u32; 3]>,
+	// Whether the resources the shader uses have been checked against the buffers bound to it.
+	bindings_checked: bool,
 }
 
 impl ComputeNode {
-	pub fn new(sequence: &ComputeSequence) -> Self {
+	pub fn new(id: ComputeSequenceId, sequence: &ComputeSequence) -> Self {
 		Self {
+			id,
 			sequence: sequence.clone(),
 			current_task: 0,
 			current_pipelines_loaded: false,
 			step_states: Vec::new(),
+			step_batches: Vec::new(),
 			iterations: 0,
+			iterations_this_frame: 0,
+			iteration_values: None,
 			group_start_time: Instant::now(),
+			pipelines_queued_at: Instant::now(),
+			paused_at: None,
+			condition_buffers: Vec::new(),
+			condition_data: HashMap::new(),
+			condition_met: false,
+			staged_copies: Vec::new(),
+			swapped_bind_groups: HashMap::new(),
+			frame_bind_groups: HashMap::new(),
+			loop_iterations: vec![0; sequence.loops.len()],
+			pipelines: HashMap::new(),
+			pipelines_queued: false,
+			all_pipelines_ready: false,
+			total_iterations: 0,
+			rate_measured_at: (Instant::now(), 0),
+			iterations_per_second: 0.0,
+			progress_sent_at: Instant::now(),
+		}
+	}
+
+	fn send_sequence_done(&self, outcome: ComputeSequenceOutcome) {
+		self
+			.sequence
+			.sender
+			.send(ComputeMessage::SequenceDone(
+				ComputeSequenceDoneEvent { sequence: self.id, outcome },
+				self.sequence.generation,
+			))
+			.unwrap();
+	}
+
+	// Tells the main world how the sequence is getting on, and throws a progress event if one is due.
+	fn send_status(&mut self) {
+		let now = Instant::now();
+		let (measured_at, measured_iterations) = self.rate_measured_at;
+		if now - measured_at >= RATE_INTERVAL {
+			self.iterations_per_second =
+				(self.total_iterations - measured_iterations) as f32 / (now - measured_at).as_secs_f32();
+			self.rate_measured_at = (now, self.total_iterations);
+		}
+		let status = ComputeSequenceStatus {
+			task: self.current_task,
+			loop_iterations: self.enclosing_loop_iterations(),
+			task_label: self.sequence.tasks[self.current_task].label.clone(),
+			iterations: self.iterations,
+			iterations_per_second: self.iterations_per_second,
+			time_in_task: self.paused_at.unwrap_or(now) - self.group_start_time,
+			compiling_pipelines: !self.current_pipelines_loaded
+				|| (self.sequence.wait_for_pipelines && !self.all_pipelines_ready),
+		};
+		if self.sequence.progress_interval.is_some_and(|interval| now - self.progress_sent_at >= interval) {
+			self.progress_sent_at = now;
+			self
+				.sequence
+				.sender
+				.send(ComputeMessage::Progress(ComputeProgressEvent { sequence: self.id, status: status.clone() }))
+				.unwrap();
+		}
+		*self.sequence.status.lock().unwrap() = Some(status);
+	}
+
+	// Ends the sequence because something went wrong, and tells the main world what it was.
+	fn fail(&mut self, task: usize, step: Option<usize>, error: String, render_buffers: &mut ShaderBufferRenderSet) {
+		let (shader, entry_point) =
+			step.map(|step| self.sequence.tasks[task].steps[step].action.shader_and_entry_point()).unwrap_or_default();
+		error!("Compute sequence {} failed in task {}: {}", self.id, task, error);
+		self
+			.sequence
+			.sender
+			.send(ComputeMessage::Error(ComputeErrorEvent { sequence: self.id, task, step, shader, entry_point, error }))
+			.unwrap();
+		self.release_task_state(render_buffers);
+		self.current_task = self.sequence.tasks.len();
+		self.send_sequence_done(ComputeSequenceOutcome::Failed);
+	}
+
+	// Finds the index of the task with the given label.
+	fn task_index(&self, label: &str) -> Result<usize, String> {
+		self
+			.sequence
+			.tasks
+			.iter()
+			.position(|task| task.label.as_deref() == Some(label))
+			.ok_or_else(|| format!("Tried to move on to the task labelled \"{}\", but there isn't one", label))
+	}
+
+	// Releases everything that was set up for the current task.
+	fn release_task_state(&mut self, render_buffers: &mut ShaderBufferRenderSet) {
+		for step in self.step_states.iter() {
+			if let ComputeAction::CopyBuffer { src } = step.step.action {
+				render_buffers.remove_copy_buffer(self.id, src);
+			}
+		}
+		for buffer in self.condition_buffers.drain(..) {
+			render_buffers.remove_copy_buffer(self.id, buffer);
+		}
+		self.step_states.clear();
+		self.step_batches.clear();
+		self.condition_data.clear();
+		self.condition_met = false;
+		self.staged_copies.clear();
+		self.swapped_bind_groups.clear();
+		self.frame_bind_groups.clear();
+		self.iterations_this_frame = 0;
+		self.iteration_values = None;
+	}
+
+	// How many times each loop the current task is in has gone round, starting with the outermost one.
+	fn enclosing_loop_iterations(&self) -> Vec<u32> {
+		self
+			.sequence
+			.loops
+			.iter()
+			.zip(&self.loop_iterations)
+			.rev()
+			.filter(|(compute_loop, _)| (compute_loop.start..compute_loop.end).contains(&self.current_task))
+			.map(|(_, iterations)| *iterations)
+			.collect()
+	}
+
+	// Works out which task follows the current one, when it's done and there isn't a particular task to move on to. Any
+	// loops that end with the current task go round again if they need to, starting with the innermost. Loops that
+	// are finished have their counts reset, in case they're reached again.
+	fn following_task(&mut self) -> Result<usize, String> {
+		let end = self.current_task + 1;
+		for (index, compute_loop) in self.sequence.loops.iter().enumerate() {
+			if compute_loop.end != end {
+				continue;
+			}
+			let repeat = match &compute_loop.kind {
+				ComputeLoopKind::Repeat(count) => self.loop_iterations[index] + 1 < count.get(),
+				ComputeLoopKind::Forever => true,
+				ComputeLoopKind::Until(condition) => {
+					let done = match self.condition_data.get(&condition.buffer) {
+						Some(data) => (condition.predicate)(data)?,
+						None => false,
+					};
+					if let Some(label) = condition.next_task.as_deref().filter(|_| done) {
+						return self.task_index(label);
+					}
+					!done
+				}
+			};
+			if repeat {
+				self.loop_iterations[index] += 1;
+				return Ok(compute_loop.start);
+			}
+			self.loop_iterations[index] = 0;
 		}
+		Ok(end)
 	}
 
+	// Reads whatever data has come back from the GPU, checking it against the task's condition, and passing on the
+	// data from CopyBuffer steps to the main world.
+	fn read_copies(&mut self, render_buffers: &mut ShaderBufferRenderSet) -> Result<(), String> {
+		let condition = self.sequence.tasks[self.current_task].condition.as_ref();
+		for buffer in self.condition_buffers.iter() {
+			for data in render_buffers.read_copies(self.id, *buffer)? {
+				if let Some(condition) = condition.filter(|condition| condition.buffer == *buffer) {
+					self.condition_met |= (condition.predicate)(&data)?;
+				}
+				self.condition_data.insert(*buffer, data);
+			}
+		}
+		for step in self.step_states.iter() {
+			if let ComputeAction::CopyBuffer { src } = step.step.action {
+				for data in render_buffers.read_copies(self.id, src)? {
+					self
+						.sequence
+						.sender
+						.send(ComputeMessage::CopyBuffer(CopyBufferEvent { sequence: self.id, buffer: src, data }))
+						.unwrap();
+				}
+			}
+		}
+		Ok(())
+	}
+
+	// Records a copy of a buffer into the staging buffer it was given this frame, to be read on a later one.
+	fn copy_back(&self, handle: ShaderBufferHandle, buffers: &ShaderBufferSet, context: &mut RenderContext) {
+		let Some((_, staging)) = self.staged_copies.iter().find(|(buffer, _)| *buffer == handle) else {
+			panic!("Somehow tried to copy {} back to the CPU without a staging buffer for it", handle);
+		};
+		let Some(src) = buffers.gpu_buffer(handle) else {
+			panic!("Somehow tried to copy from buffer {}, which doesn't exist", handle);
+		};
+		context.command_encoder().copy_buffer_to_buffer(&src, 0, staging, 0, staging.size());
+	}
+
+	// Cleans up after the current task, and tells the main world it's done.
+	fn end_task(
+		&mut self, next_task: usize, reason: ComputeTaskEndReason, loop_iterations: Vec<u32>,
+		render_buffers: &mut ShaderBufferRenderSet,
+	) {
+		self.release_task_state(render_buffers);
+		let now = Instant::now();
+		self
+			.sequence
+			.sender
+			.send(ComputeMessage::GroupDone(ComputeTaskDoneEvent {
+				sequence: self.id,
+				group_finished: self.current_task,
+				group_finished_label: self.sequence.tasks[self.current_task].label.clone(),
+				time_in_group: now - self.group_start_time,
+				final_group: next_task >= self.sequence.tasks.len(),
+				reason,
+				loop_iterations,
+			}))
+			.unwrap();
+		// Loops that have been left behind start counting from zero if they're reached again.
+		for (compute_loop, iterations) in self.sequence.loops.iter().zip(self.loop_iterations.iter_mut()) {
+			if !(compute_loop.start..compute_loop.end).contains(&next_task) {
+				*iterations = 0;
+			}
+		}
+		self.current_task = next_task;
+		self.current_pipelines_loaded = false;
+		self.iterations = 0;
+		self.group_start_time = now;
+	}
+
+	// Dispatches the shader for a step, as part of a compute pass that may be shared with other steps.
 	fn run_shader(
-		&self, pipeline_id: CachedComputePipelineId, x_workgroup_size: u32, y_workgroup_size: u32, z_workgroup_size: u32,
-		world: &World, render_context: &mut RenderContext,
+		&self, pass: &mut ComputePass, step: &ComputeStepState, bind_groups: &[BindGroup], buffers: &ShaderBufferSet,
+		gpu_images: &RenderAssets<GpuImage>,
 	) {
-		let pipeline_cache = world.resource::<PipelineCache>();
-		let bind_groups = world.resource::<ComputeBindGroups>();
-		let Some(pipeline) = pipeline_cache.get_compute_pipeline(pipeline_id) else {
-			panic!("Somehow running the shader without all the shader pipelines being loaded");
+		let Some(pipeline) = &step.pipeline else {
+			panic!("Somehow got to trying to run a shader step with no pipeline");
 		};
-		let encoder = render_context.command_encoder();
-		{
-			let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor::default());
-			pass.set_pipeline(pipeline);
-			for (i, bind_group) in bind_groups.0.iter().enumerate() {
+		pass.set_pipeline(pipeline);
+		if let Some(groups) = step.step.action.bind_groups() {
+			for (i, group) in groups.iter().enumerate() {
+				pass.set_bind_group(i as u32, &bind_groups[*group as usize], &[]);
+			}
+		} else {
+			for (i, bind_group) in bind_groups.iter().enumerate() {
 				pass.set_bind_group(i as u32, bind_group, &[]);
 			}
-			pass.dispatch_workgroups(x_workgroup_size, y_workgroup_size, z_workgroup_size);
+		}
+		let push_constants = step.step.action.push_constants();
+		if !push_constants.is_empty() {
+			pass.set_push_constants(0, push_constants);
+		}
+		match &step.step.action {
+			ComputeAction::RunShader { dispatch, .. } => {
+				let (x, y, z) = dispatch.workgroup_counts(step.workgroup_size.unwrap_or([1, 1, 1]), buffers, gpu_images);
+				pass.dispatch_workgroups(x, y, z);
+			}
+			ComputeAction::RunShaderIndirect { .. } => {
+				let Some(indirect_buffer) = &step.indirect_buffer else {
+					panic!("Somehow got to trying to run a RunShaderIndirect action step with no indirect buffer");
+				};
+				pass.dispatch_workgroups_indirect(indirect_buffer, 0);
+			}
+			_ => panic!("Somehow tried to dispatch a step that doesn't run a shader"),
 		}
 	}
 }
 
+// Push constants are an optional feature, so it's best to find out they won't work before trying to use them.
+fn validate_push_constants(device: &RenderDevice, push_constants: &[u8]) -> Result<(), String> {
+	if push_constants.is_empty() {
+		return Ok(());
+	}
+	if !device.features().contains(WgpuFeatures::PUSH_CONSTANTS) {
+		return Err("Tried to use push constants, but the PUSH_CONSTANTS feature isn't enabled on this device".to_owned());
+	}
+	if !push_constants.len().is_multiple_of(4) {
+		return Err(format!("Tried to use {} bytes of push constants, which isn't a multiple of 4", push_constants.len()));
+	}
+	let max_size = device.limits().max_push_constant_size;
+	if push_constants.len() > max_size as usize {
+		return Err(format!(
+			"Tried to use {} bytes of push constants, but this device only supports {}",
+			push_constants.len(),
+			max_size
+		));
+	}
+	Ok(())
+}
+
+// Queues the pipeline for a step that runs a shader.
+fn queue_pipeline(
+	pipeline_cache: &mut PipelineCache, buffers: &ShaderBufferSet, device: &RenderDevice, shader: Handle<Shader>,
+	shader_defs: Vec<ShaderDefVal>, action: &ComputeAction,
+) -> Result<CachedComputePipelineId, String> {
+	let (ComputeAction::RunShader { entry_point, .. } | ComputeAction::RunShaderIndirect { entry_point, .. }) = action
+	else {
+		panic!("Somehow tried to queue a pipeline for a step that doesn't run a shader");
+	};
+	let push_constants = action.push_constants();
+	let push_constant_ranges = if push_constants.is_empty() {
+		Vec::new()
+	} else {
+		vec![PushConstantRange { stages: ShaderStages::COMPUTE, range: 0..push_constants.len() as u32 }]
+	};
+	let mut layout = buffers.bind_group_layouts(device);
+	if let Some(groups) = action.bind_groups() {
+		layout = groups
+			.iter()
+			.map(|group| {
+				layout
+					.get(*group as usize)
+					.cloned()
+					.ok_or_else(|| format!("Tried to run {} with bind group {}, which doesn't exist", entry_point, group))
+			})
+			.collect::<Result<_, _>>()?;
+	}
+	Ok(pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+		label: None,
+		layout,
+		push_constant_ranges,
+		shader,
+		shader_defs,
+		entry_point: Cow::Owned(entry_point.clone()),
+		zero_initialize_workgroup_memory: true,
+	}))
+}
+
+// Everything that goes into making a pipeline, so that steps that would make identical ones can share them.
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct PipelineKey {
+	variant: ShaderVariant,
+	entry_point: String,
+	push_constants_size: usize,
+	bind_groups: Option<Vec<u32>>,
+}
+
+impl PipelineKey {
+	fn new(action: &ComputeAction) -> Option<Self> {
+		let (ComputeAction::RunShader { entry_point, .. } | ComputeAction::RunShaderIndirect { entry_point, .. }) = action
+		else {
+			return None;
+		};
+		Some(Self {
+			variant: action.shader_variant()?,
+			entry_point: entry_point.clone(),
+			push_constants_size: action.push_constants().len(),
+			bind_groups: action.bind_groups().map(|groups| groups.to_vec()),
+		})
+	}
+}
+
+// A pipeline that's been queued for a sequence.
+struct QueuedPipeline {
+	id: CachedComputePipelineId,
+	// For shaders with override constants, the copy with the constants applied that the pipeline was made from.
+	derived_shader: Option<Handle<Shader>>,
+}
+
+// Gets the pipeline for a step that runs a shader, queueing it if it hasn't been already. Shaders with override
+// constants have to wait for the copy with the constants applied to be made, in which case this returns None, and they
+// get a new pipeline whenever that copy is made again.
+fn pipeline_id(
+	pipelines: &mut HashMap<PipelineKey, QueuedPipeline>, action: &ComputeAction, pipeline_cache: &mut PipelineCache,
+	buffers: &ShaderBufferSet, device: &RenderDevice, asset_server: &AssetServer, reflections: &ShaderReflections,
+) -> Result<Option<CachedComputePipelineId>, String> {
+	let Some(key) = PipelineKey::new(action) else {
+		panic!("Somehow tried to get a pipeline for a step that doesn't run a shader");
+	};
+	if key.variant.constants.is_empty() {
+		if let Some(queued) = pipelines.get(&key) {
+			return Ok(Some(queued.id));
+		}
+		let shader = asset_server.load(&key.variant.shader);
+		let id = queue_pipeline(pipeline_cache, buffers, device, shader, key.variant.shader_defs.clone(), action)?;
+		pipelines.insert(key, QueuedPipeline { id, derived_shader: None });
+		return Ok(Some(id));
+	}
+
+	// If the copy is being made again, or couldn't be, then the last pipeline keeps being used.
+	let reflected = match reflections.get(&key.variant) {
+		Some(Ok(reflected)) => reflected,
+		Some(Err(e)) => {
+			return match pipelines.get(&key) {
+				Some(queued) => Ok(Some(queued.id)),
+				None => Err(format!("Couldn't apply the override constants: {}", e)),
+			}
+		}
+		None => return Ok(pipelines.get(&key).map(|queued| queued.id)),
+	};
+	if let Some(queued) = pipelines.get(&key).filter(|queued| queued.derived_shader == reflected.shader) {
+		return Ok(Some(queued.id));
+	}
+	let shader = reflected.shader.clone().unwrap();
+	let id = queue_pipeline(pipeline_cache, buffers, device, shader.clone(), vec![], action)?;
+	pipelines.insert(key, QueuedPipeline { id, derived_shader: Some(shader) });
+	Ok(Some(id))
+}
+
 impl Node for ComputeNode {
 	fn update(&mut self, world: &mut World) {
+		let mut system_state: SystemState<ComputeNodeUpdateParams> = SystemState::new(world);
+		let (
+			mut buffers,
+			mut render_buffers,
+			device,
+			render_queue,
+			sequences,
+			mut pipeline_cache,
+			asset_server,
+			reflections,
+			gpu_images,
+		) = system_state.get_mut(world);
+
+		// The main world drops sequences once they're done, so there's nothing left to do.
+		let Some(sequence) = sequences.0.get(&self.id) else {
+			return;
+		};
+
+		// If a new sequence has been started with this id, either after the last one finished or to replace it, then
+		// anything left over from the old one is released, and the new one starts from the beginning.
+		if sequence.generation != self.sequence.generation {
+			self.release_task_state(&mut render_buffers);
+			if self.current_task < self.sequence.tasks.len() {
+				self.send_sequence_done(ComputeSequenceOutcome::Replaced);
+			}
+			*self = Self::new(self.id, sequence);
+		}
+
 		// All the tasks have been completed, so there's nothing to do.
 		if self.current_task >= self.sequence.tasks.len() {
 			return;
 		}
 
-		let mut system_state: SystemState<(
-			ResMut<ShaderBufferSet>,
-			ResMut<ShaderBufferRenderSet>,
-			Res<RenderDevice>,
-			Res<RenderQueue>,
-			Res<ComputeSequence>,
-			ResMut<PipelineCache>,
-			Res<AssetServer>,
-		)> = SystemState::new(world);
-		let (mut buffers, mut render_buffers, device, render_queue, sequence, mut pipeline_cache, asset_server) =
-			system_state.get_mut(world);
+		// The status covers everything up to the iterations that ran last frame.
+		self.send_status();
 
-		let group = &self.sequence.tasks[self.current_task];
+		// Every pipeline the sequence needs is queued as soon as it starts, so that moving on to the next task doesn't
+		// have to wait for its pipelines to compile. Any problems are reported once the task that has the step starts.
+		if !self.pipelines_queued {
+			for step in self.sequence.tasks.iter().flat_map(|task| task.steps.iter()) {
+				if step.action.shader_variant().is_some() {
+					pipeline_id(
+						&mut self.pipelines,
+						&step.action,
+						&mut pipeline_cache,
+						&buffers,
+						&device,
+						&asset_server,
+						&reflections,
+					)
+					.ok();
+				}
+			}
+			pipeline_cache.process_queue();
+			self.pipelines_queued = true;
+		}
+
+		// Stop and pause requests from the main world are only honored here, between iterations.
+		match sequence.control {
+			ComputeControl::Stop => {
+				self.release_task_state(&mut render_buffers);
+				self.current_task = self.sequence.tasks.len();
+				self.send_sequence_done(ComputeSequenceOutcome::Cancelled);
+				return;
+			}
+			ComputeControl::Pause => {
+				self.paused_at.get_or_insert_with(Instant::now);
+				return;
+			}
+			ComputeControl::Run => {
+				// Time spent paused shouldn't count as time spent in the task.
+				if let Some(paused_at) = self.paused_at.take() {
+					self.group_start_time += Instant::now() - paused_at;
+					self.pipelines_queued_at += Instant::now() - paused_at;
+				}
+			}
+		}
 
-		// If there's a maximum number of iterations, check if it's been reached.
-		// If it has, clean up after this task and move on to the next.
-		// This is an assignment, as it has to update the extracted group if the
-		// group is complete.
-		let group = if let Some(max_iterations) = group.iterations {
-			if self.iterations >= max_iterations.get() {
-				for step in self.step_states.iter() {
-					if let ComputeAction::CopyBuffer { src } = step.step.action {
-						render_buffers.remove_copy_buffer(src);
+		// If the sequence has been asked to, it waits for every pipeline in every task to be ready before it starts.
+		if self.sequence.wait_for_pipelines && !self.all_pipelines_ready {
+			let mut failure = None;
+			// The first step whose pipeline isn't ready yet, to blame if they take too long.
+			let mut waiting_on = None;
+			'tasks: for (task_index, task) in self.sequence.tasks.iter().enumerate() {
+				for (step_index, step) in task.steps.iter().enumerate() {
+					if step.action.shader_variant().is_none() {
+						continue;
+					}
+					let id = pipeline_id(
+						&mut self.pipelines,
+						&step.action,
+						&mut pipeline_cache,
+						&buffers,
+						&device,
+						&asset_server,
+						&reflections,
+					);
+					let error = match id {
+						Ok(Some(id)) => match pipeline_cache.get_compute_pipeline_state(id) {
+							CachedPipelineState::Ok(_) => None,
+							CachedPipelineState::Err(e) => Some(e.to_string()),
+							_ => {
+								waiting_on.get_or_insert((task_index, step_index));
+								None
+							}
+						},
+						Ok(None) => {
+							waiting_on.get_or_insert((task_index, step_index));
+							None
+						}
+						Err(e) => Some(e),
+					};
+					if let Some(error) = error {
+						failure = Some((task_index, Some(step_index), error));
+						break 'tasks;
 					}
 				}
-				let now = Instant::now();
-				self.current_task += 1;
-				self.current_pipelines_loaded = false;
-				self.step_states.clear();
-				self.iterations = 0;
-				self
-					.sequence
-					.sender
-					.send(ComputeMessage::GroupDone(ComputeTaskDoneEvent {
-						group_finished: self.current_task - 1,
-						group_finished_label: group.label.clone(),
-						time_in_group: now - self.group_start_time,
-						final_group: self.current_task == self.sequence.tasks.len(),
-					}))
-					.unwrap();
-				self.group_start_time = now;
-				// All the tasks have been completed, so there's nothing to do.
-				if self.current_task >= self.sequence.tasks.len() {
+			}
+			if let Some((task, step)) = waiting_on.filter(|_| failure.is_none()) {
+				if self.pipelines_queued_at.elapsed() > self.sequence.pipeline_timeout {
+					let error = format!(
+						"The pipeline still wasn't ready after {:?}, so the sequence couldn't start",
+						self.sequence.pipeline_timeout
+					);
+					failure = Some((task, Some(step), error));
+				}
+			}
+			if let Some((task, step, error)) = failure {
+				self.fail(task, step, error, &mut render_buffers);
+				return;
+			}
+			if waiting_on.is_some() {
+				return;
+			}
+			self.all_pipelines_ready = true;
+			// Waiting for the pipelines shouldn't count as time spent in the first task.
+			self.group_start_time = Instant::now();
+		}
+
+		// Pick up whatever's come back from the GPU since last frame. Nothing here waits on the GPU, so data arrives a
+		// frame or two after the iteration it came from.
+		render_buffers.map_staged_copies(self.id);
+		device.poll(Maintain::Poll);
+		if let Err(e) = self.read_copies(&mut render_buffers) {
+			self.fail(self.current_task, None, e, &mut render_buffers);
+			return;
+		}
+
+		// Work out whether the current task is done, and if so, which task comes next. The condition is checked
+		// first, so that it still gets to pick the next task if the task runs out of iterations at the same time.
+		let task = &self.sequence.tasks[self.current_task];
+		let mut task_end = None;
+		if let Some(condition) = task.condition.as_ref().filter(|_| self.condition_met) {
+			task_end = Some((condition.next_task.clone(), ComputeTaskEndReason::Condition));
+		}
+		if task_end.is_none() && task.iterations.is_some_and(|max_iterations| self.iterations >= max_iterations.get()) {
+			task_end = Some((None, ComputeTaskEndReason::Iterations));
+		}
+		if task_end.is_none() && task.duration.is_some_and(|duration| self.group_start_time.elapsed() >= duration) {
+			task_end = Some((None, ComputeTaskEndReason::Duration));
+		}
+
+		// A task that's done waits for the rest of its copies to come back before it ends, so none of its data is lost,
+		// and its condition, and the conditions of any loops it ends, see the results of its last iteration.
+		if task_end.is_some() && render_buffers.copies_pending(self.id) {
+			self.iterations_this_frame = 0;
+			self.staged_copies.clear();
+			return;
+		}
+
+		// If it is done, clean up after this task and move on to the next.
+		if let Some((label, reason)) = task_end {
+			let loop_iterations = self.enclosing_loop_iterations();
+			let next_task = match label {
+				Some(label) => self.task_index(&label),
+				None => self.following_task(),
+			};
+			let next_task = match next_task {
+				Ok(next_task) => next_task,
+				Err(e) => {
+					self.fail(self.current_task, None, e, &mut render_buffers);
 					return;
 				}
-				&self.sequence.tasks[self.current_task]
-			} else {
-				group
+			};
+			self.end_task(next_task, reason, loop_iterations, &mut render_buffers);
+			// All the tasks have been completed, so there's nothing left to do.
+			if self.current_task >= self.sequence.tasks.len() {
+				self.send_sequence_done(ComputeSequenceOutcome::Finished);
+				return;
 			}
-		} else {
-			group
-		};
+		}
+		let group = &self.sequence.tasks[self.current_task];
 
 		// If step_states is empty, this must be the first iteration on a new group,
 		// so it's time to initialize the step_states, which includes setting up all
 		// the pipelines in the PipelineCache.
-		if self.step_states.len() == 0 {
-			for step in group.steps.iter() {
+		if self.step_states.is_empty() {
+			match step_batches(group) {
+				Ok(batches) => self.step_batches = batches,
+				Err(e) => {
+					self.fail(self.current_task, None, e, &mut render_buffers);
+					return;
+				}
+			}
+			let loop_conditions = self.sequence.loops.iter().filter_map(|compute_loop| match &compute_loop.kind {
+				ComputeLoopKind::Until(condition) if compute_loop.end == self.current_task + 1 => Some(condition.buffer),
+				_ => None,
+			});
+			let mut failure = None;
+			for buffer in group.condition.iter().map(|condition| condition.buffer).chain(loop_conditions) {
+				if !self.condition_buffers.contains(&buffer) {
+					if let Err(e) = render_buffers.create_copy_buffer(self.id, buffer, &buffers) {
+						failure = Some((None, e));
+						break;
+					}
+					self.condition_buffers.push(buffer);
+				}
+			}
+			for (index, step) in group.steps.iter().enumerate() {
+				if failure.is_some() {
+					break;
+				}
+				let variant = step.action.shader_variant();
+				let shader = variant.as_ref().map(|variant| asset_server.load(&variant.shader));
+				let validation = if step.schedule == ComputeSchedule::LastIteration && group.iterations.is_none() {
+					Err("The step is scheduled for the last iteration of a task with no fixed number of iterations".to_owned())
+				} else {
+					match &step.action {
+						ComputeAction::RunShaderIndirect { args, offset, .. } => buffers.validate_indirect_args(*args, *offset),
+						ComputeAction::ClearBuffer { buffer, range, side } => {
+							buffers.validate_buffer_write(*buffer, *side, range.clone(), "clear")
+						}
+						ComputeAction::FillBuffer { buffer, side, .. } => {
+							buffers.validate_buffer_write(*buffer, *side, None, "fill")
+						}
+						_ => Ok(()),
+					}
+				};
+				let validation = validation.and_then(|_| validate_push_constants(&device, step.action.push_constants()));
+				let id = validation.and_then(|_| match variant {
+					Some(_) => pipeline_id(
+						&mut self.pipelines,
+						&step.action,
+						&mut pipeline_cache,
+						&buffers,
+						&device,
+						&asset_server,
+						&reflections,
+					),
+					None => Ok(None),
+				});
+				let id = match id {
+					Ok(id) => id,
+					Err(e) => {
+						failure = Some((Some(index), e));
+						break;
+					}
+				};
 				if let ComputeAction::CopyBuffer { src } = step.action {
-					render_buffers.create_copy_buffer(src, &buffers, &device);
+					if let Err(e) = render_buffers.create_copy_buffer(self.id, src, &buffers) {
+						failure = Some((Some(index), e));
+						break;
+					}
 				}
-				let id = if let ComputeAction::RunShader { shader, entry_point, .. } = &step.action {
-					let bind_group_layouts = buffers.bind_group_layouts(&device);
-					let shader = asset_server.load(shader);
-					Some(pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+				let indirect_buffer = if let ComputeAction::RunShaderIndirect { .. } = step.action {
+					Some(device.create_buffer(&BufferDescriptor {
+						label: None,
+						size: INDIRECT_ARGS_SIZE,
+						usage: BufferUsages::INDIRECT | BufferUsages::COPY_DST,
+						mapped_at_creation: false,
+					}))
+				} else {
+					None
+				};
+				let fill_buffer = if let ComputeAction::FillBuffer { buffer, value, side } = step.action {
+					let size = buffers.gpu_buffer_side(buffer, side).map_or(0, |buffer| buffer.size()).min(FILL_CHUNK_SIZE);
+					Some(device.create_buffer_with_data(&BufferInitDescriptor {
 						label: None,
-						layout: bind_group_layouts.clone(),
-						push_constant_ranges: Vec::new(),
-						shader: shader,
-						shader_defs: vec![],
-						entry_point: Cow::Owned(entry_point.clone()),
-						zero_initialize_workgroup_memory: true,
+						contents: &value.to_le_bytes().repeat(size as usize / 4),
+						usage: BufferUsages::COPY_SRC,
 					}))
 				} else {
 					None
@@ -159,71 +819,324 @@ impl Node for ComputeNode {
 				self.step_states.push(ComputeStepState {
 					step: step.clone(),
 					id,
-					last_run_time: if let Some(max_frequency) = step.max_frequency {
+					variant,
+					shader,
+					pipeline: None,
+					reload_failed: false,
+					last_run_time: if let ComputeSchedule::MaxFrequency(max_frequency) = step.schedule {
 						Instant::now() - Duration::from_secs_f32(2.0 / max_frequency.get() as f32)
 					} else {
 						Instant::now()
 					},
-					run_this_time: true,
-					copy_buffer_ready: true,
+					runs: Vec::new(),
+					indirect_buffer,
+					fill_buffer,
+					workgroup_size: None,
+					bindings_checked: false,
 				});
 			}
 			pipeline_cache.process_queue();
+			self.pipelines_queued_at = Instant::now();
+			if let Some((step, error)) = failure {
+				self.fail(self.current_task, step, error, &mut render_buffers);
+				return;
+			}
 		}
 
-		// If the pipelines have not been marked as loaded, check them.
+		// If the pipelines have not been marked as loaded, check them, along with
+		// the workgroup sizes of any shaders that need them.
 		// If they're loaded, mark them as such. Otherwise we can't continue yet.
+		// If anything has gone wrong with them, or they've taken too long, the sequence fails.
 		if !self.current_pipelines_loaded {
-			let step_states = self.step_states.iter().flat_map(|step| {
+			let mut loaded = true;
+			let mut failure = None;
+			for (index, step) in self.step_states.iter_mut().enumerate() {
+				let Some(variant) = &step.variant else {
+					continue;
+				};
+				if let Some(LoadState::Failed(e)) =
+					step.shader.as_ref().and_then(|shader| asset_server.get_load_state(shader.id()))
+				{
+					failure = Some((index, format!("Couldn't load the shader: {}", e)));
+					break;
+				}
+				// The bindings are checked before anything else, since a mismatch would otherwise only show up as a
+				// much less helpful error from wgpu when the pipeline is made. If the shader can't be parsed, or
+				// doesn't have the entry point, the sequence fails with whatever went wrong.
+				if !step.bindings_checked {
+					let (ComputeAction::RunShader { entry_point, .. } | ComputeAction::RunShaderIndirect { entry_point, .. }) =
+						&step.step.action
+					else {
+						continue;
+					};
+					match reflections.binding_mismatches(variant, entry_point, step.step.action.bind_groups(), &buffers) {
+						Some(Ok(mismatches)) if !mismatches.is_empty() => {
+							let error = format!("The shader's bindings don't match the buffers: {}", mismatches.join("; "));
+							failure = Some((index, error));
+							break;
+						}
+						Some(Ok(_)) => step.bindings_checked = true,
+						Some(Err(e)) => {
+							failure = Some((index, format!("Couldn't check the shader's bindings: {}", e)));
+							break;
+						}
+						None => {
+							loaded = false;
+							continue;
+						}
+					}
+				}
+				if step.id.is_none() {
+					match pipeline_id(
+						&mut self.pipelines,
+						&step.step.action,
+						&mut pipeline_cache,
+						&buffers,
+						&device,
+						&asset_server,
+						&reflections,
+					) {
+						Ok(Some(id)) => {
+							step.id = Some(id);
+							pipeline_cache.process_queue();
+						}
+						Ok(None) => loaded = false,
+						Err(e) => {
+							failure = Some((index, e));
+							break;
+						}
+					}
+				}
 				if let Some(id) = step.id {
-					Some(pipeline_cache.get_compute_pipeline_state(id))
-				} else {
-					None
+					match pipeline_cache.get_compute_pipeline_state(id) {
+						CachedPipelineState::Ok(_) => {}
+						CachedPipelineState::Err(e) => {
+							failure = Some((index, e.to_string()));
+							break;
+						}
+						_ => loaded = false,
+					}
 				}
-			});
-			let state = step_states.fold(Some(Ok(())), |acc, x| match (acc, x) {
-				(None, _) => None,
-				(Some(Err(e)), _) => Some(Err(e)),
-				(Some(Ok(_)), CachedPipelineState::Ok(_)) => Some(Ok(())),
-				(Some(Ok(_)), CachedPipelineState::Err(e)) => Some(Err(e)),
-				(Some(Ok(_)), _) => None,
-			});
-			self.current_pipelines_loaded = match state {
-				Some(Ok(_)) => true,
-				Some(Err(e)) => panic!("{}", e),
-				None => false,
+				if let ComputeAction::RunShader { entry_point, dispatch, .. } = &step.step.action {
+					if dispatch.needs_workgroup_size() && step.workgroup_size.is_none() {
+						match reflections.workgroup_size(variant, entry_point) {
+							Some(Ok(workgroup_size)) => step.workgroup_size = Some(workgroup_size),
+							Some(Err(e)) => {
+								failure = Some((index, format!("Couldn't read the workgroup size: {}", e)));
+								break;
+							}
+							None => loaded = false,
+						}
+					}
+				}
+			}
+			if failure.is_none() && !loaded && self.pipelines_queued_at.elapsed() > self.sequence.pipeline_timeout {
+				let stuck = self.step_states.iter().position(|step| {
+					step.variant.is_some()
+						&& !step
+							.id
+							.is_some_and(|id| matches!(pipeline_cache.get_compute_pipeline_state(id), CachedPipelineState::Ok(_)))
+				});
+				failure = stuck
+					.map(|index| (index, format!("The pipeline still wasn't ready after {:?}", self.sequence.pipeline_timeout)));
+			}
+			if let Some((step, error)) = failure {
+				self.fail(self.current_task, Some(step), error, &mut render_buffers);
+				return;
+			}
+			self.current_pipelines_loaded = loaded;
+		}
+
+		// Shaders can still change on disk once a task's pipelines are loaded. Bevy's pipeline cache recompiles the
+		// pipelines that use them, and the new ones are swapped in here, between iterations. Until they're ready, or
+		// if they fail to compile, the last pipeline that worked keeps being used.
+		if self.current_pipelines_loaded {
+			for step in self.step_states.iter_mut() {
+				let Some(variant) = &step.variant else {
+					continue;
+				};
+				// Shaders with override constants are copied again when they change, so they need a new pipeline.
+				if !variant.constants.is_empty() {
+					match pipeline_id(
+						&mut self.pipelines,
+						&step.step.action,
+						&mut pipeline_cache,
+						&buffers,
+						&device,
+						&asset_server,
+						&reflections,
+					) {
+						Ok(Some(id)) => step.id = Some(id),
+						Ok(None) => {}
+						Err(e) => warn!("Couldn't reload {}, so the last version will keep being used: {}", variant.shader, e),
+					}
+				}
+				let Some(id) = step.id else {
+					continue;
+				};
+				if let Some(pipeline) = pipeline_cache.get_compute_pipeline(id) {
+					if step.pipeline.as_ref().is_none_or(|current| current.id() != pipeline.id()) {
+						step.pipeline = Some(pipeline.clone());
+						step.reload_failed = false;
+						if let ComputeAction::RunShader { entry_point, .. } = &step.step.action {
+							if let Some(Ok(workgroup_size)) = reflections.workgroup_size(variant, entry_point) {
+								step.workgroup_size = Some(workgroup_size);
+							}
+						}
+					}
+				} else if let CachedPipelineState::Err(e) = pipeline_cache.get_compute_pipeline_state(id) {
+					if !step.reload_failed {
+						warn!("Couldn't reload {}, so the last version will keep being used: {}", variant.shader, e);
+						step.reload_failed = true;
+					}
+				}
+			}
+		}
+
+		// Buffers can be deleted while a task is running, so before anything's recorded, the task's steps are checked
+		// against the buffers again, and anything that's gone fails the sequence here rather than panicking part way
+		// through recording the frame. Textures that haven't made it to the GPU yet just hold things up for a frame.
+		let mut buffers_ready = true;
+		if self.current_pipelines_loaded {
+			let mut failure = self
+				.step_states
+				.iter()
+				.enumerate()
+				.find_map(|(index, step)| buffers.step_errors(&step.step).into_iter().next().map(|e| (Some(index), e)));
+			if let Some(buffer) = self.sequence.iteration_buffer.filter(|buffer| buffers.gpu_buffer(*buffer).is_none()) {
+				failure = failure.or(Some((None, format!("The iteration buffer {} no longer exists", buffer))));
+			}
+			if let Some((step, error)) = failure {
+				self.fail(self.current_task, step, error, &mut render_buffers);
+				return;
 			}
+			buffers_ready = self.step_states.iter().all(|step| match step.step.action {
+				ComputeAction::RunShader { dispatch: DispatchSize::PerTexel(texture), .. } => {
+					buffers.texture_size(texture, &gpu_images).is_some()
+				}
+				_ => true,
+			});
 		}
 
 		// If the pipelines are actually loaded now, then:
+		// - work out how many iterations to run this frame, without going past the end of the task
 		// - update the iteration buffer, if there is one
-		// - for every step:
-		//   - if it has a frequency limit, check if it should run this frame
-		//   - if it's a buffer copy, alternate whether it copies into or out of the
-		//     copy buffer
-		if self.current_pipelines_loaded {
+		// - for every step, on every iteration:
+		//   - if it has a frequency limit, check if it should run this iteration
+		// - if there's a condition, its buffer will be copied at the end of this
+		//   frame's iterations, so it can be checked once the data comes back
+		// - every buffer that's copied back gets a staging buffer to copy into
+		self.iterations_this_frame = 0;
+		self.iteration_values = None;
+		self.staged_copies.clear();
+		self.frame_bind_groups.clear();
+		if self.current_pipelines_loaded && buffers_ready {
+			let mut iterations = group.iterations_per_frame.map_or(1, |iterations| iterations.get());
+			if let Some(max_iterations) = group.iterations {
+				iterations = iterations.min(max_iterations.get() - self.iterations);
+			}
 			if let Some(buffer) = sequence.iteration_buffer {
 				buffers.set_buffer(buffer, self.iterations, &render_queue);
+				if iterations > 1 {
+					let values = (self.iterations + 1..self.iterations + iterations)
+						.flat_map(|iteration| iteration.to_le_bytes())
+						.collect::<Vec<_>>();
+					self.iteration_values = Some(device.create_buffer_with_data(&BufferInitDescriptor {
+						label: None,
+						contents: &values,
+						usage: BufferUsages::COPY_SRC,
+					}));
+				}
 			}
-			self.iterations += 1;
+			let first_iteration = self.iterations;
+			self.iterations += iterations;
+			self.total_iterations += iterations as u64;
+			self.iterations_this_frame = iterations;
 
 			for step in self.step_states.iter_mut() {
-				step.run_this_time = if let Some(max_frequency) = step.step.max_frequency {
-					let now = Instant::now();
-					if now - step.last_run_time > Duration::from_secs_f32(1.0 / max_frequency.get() as f32) {
-						step.last_run_time = now;
-						true
-					} else {
+				let is_copy = matches!(step.step.action, ComputeAction::CopyBuffer { .. });
+				step.runs.clear();
+				for iteration in first_iteration..first_iteration + iterations {
+					let run = if is_copy && step.runs.contains(&true) {
 						false
+					} else {
+						match step.step.schedule {
+							ComputeSchedule::Always => true,
+							ComputeSchedule::Every(n) => iteration.is_multiple_of(n.get()),
+							ComputeSchedule::FirstIteration => iteration == 0,
+							ComputeSchedule::LastIteration => {
+								group.iterations.is_some_and(|max_iterations| iteration == max_iterations.get() - 1)
+							}
+							ComputeSchedule::MaxFrequency(max_frequency) => {
+								let now = Instant::now();
+								if now - step.last_run_time > Duration::from_secs_f32(1.0 / max_frequency.get() as f32) {
+									step.last_run_time = now;
+									true
+								} else {
+									false
+								}
+							}
+						}
+					};
+					step.runs.push(run);
+				}
+			}
+
+			// Every buffer that's copied back this frame gets a staging buffer to copy into.
+			let copied: Vec<ShaderBufferHandle> = self
+				.condition_buffers
+				.iter()
+				.copied()
+				.chain(self.step_states.iter().filter_map(|step| match step.step.action {
+					ComputeAction::CopyBuffer { src } if step.runs.contains(&true) => Some(src),
+					_ => None,
+				}))
+				.collect();
+			for buffer in copied {
+				match render_buffers.stage_copy(self.id, buffer, &buffers, &device) {
+					Ok(staging) => self.staged_copies.push((buffer, staging)),
+					Err(e) => {
+						self.fail(self.current_task, None, e, &mut render_buffers);
+						return;
 					}
-				} else {
-					true
-				};
+				}
+			}
 
-				if step.run_this_time {
-					step.copy_buffer_ready = !step.copy_buffer_ready;
+			// Work out every combination of swaps the frame's iterations go through, in the same order they'll run in,
+			// and make sure there are bind groups for each. Ones that are already bound the same way are reused.
+			let mut swapped: Vec<ShaderBufferHandle> = Vec::new();
+			let mut used = Vec::new();
+			for iteration in 0..iterations as usize {
+				for &index in self.step_batches.iter().flatten() {
+					let step = &self.step_states[index];
+					let ComputeAction::SwapBuffers { buffer } = step.step.action else {
+						continue;
+					};
+					if !step.runs[iteration] {
+						continue;
+					}
+					match swapped.binary_search(&buffer) {
+						Ok(index) => {
+							swapped.remove(index);
+						}
+						Err(index) => swapped.insert(index, buffer),
+					}
+					if !swapped.is_empty() && !self.frame_bind_groups.contains_key(&swapped) {
+						let bound = buffers.bound_ids(&swapped, &gpu_images);
+						let bind_groups = self
+							.swapped_bind_groups
+							.entry(bound.clone())
+							.or_insert_with(|| buffers.swapped_bind_groups(&swapped, &device, &gpu_images));
+						self.frame_bind_groups.insert(swapped.clone(), bind_groups.clone());
+						used.push(bound);
+					}
 				}
 			}
+			// Bind groups for resources that have since been replaced are no use any more. Frames that don't swap
+			// anything leave them be, for steps that only swap every so often.
+			if !used.is_empty() {
+				self.swapped_bind_groups.retain(|bound, _| used.contains(bound));
+			}
 		}
 	}
 
@@ -235,44 +1148,217 @@ impl Node for ComputeNode {
 			return Ok(());
 		}
 
-		// If the current pipelines aren't loaded yet, then we can't do anything
-		// this frame.
-		if !self.current_pipelines_loaded {
+		// If the current pipelines aren't loaded yet, or the sequence is paused,
+		// then we can't do anything this frame.
+		if !self.current_pipelines_loaded || self.paused_at.is_some() {
 			return Ok(());
 		}
 
-		let device = world.resource::<RenderDevice>();
 		let buffers = world.resource::<ShaderBufferSet>();
-		let render_buffers = world.resource::<ShaderBufferRenderSet>();
+		let gpu_images = world.resource::<RenderAssets<GpuImage>>();
+		let iteration_buffer = self.sequence.iteration_buffer.and_then(|buffer| buffers.gpu_buffer(buffer));
 
-		// Iterate over all the steps and run them.
-		for step in self.step_states.iter() {
-			if !step.run_this_time {
-				continue;
+		// Double buffers swapped part way through the frame, and the bind groups to use with them swapped. The main
+		// world is only told about the swaps at the end of the frame.
+		let mut swapped = Vec::new();
+		let mut bind_groups = &world.resource::<ComputeBindGroups>().0;
+
+		// Iterate over all of this frame's iterations, and all the steps in each, and run them.
+		for iteration in 0..self.iterations_this_frame as usize {
+			if let (Some(iteration_buffer), Some(iteration_values)) = (&iteration_buffer, &self.iteration_values) {
+				if iteration > 0 {
+					context.command_encoder().copy_buffer_to_buffer(
+						iteration_values,
+						(iteration as u64 - 1) * 4,
+						iteration_buffer,
+						0,
+						4,
+					);
+				}
 			}
 
-			match step.step.action {
-				ComputeAction::CopyBuffer { src } => {
-					if step.copy_buffer_ready {
-						let data = render_buffers.copy_from_copy_buffer_to_vec(src, device);
-						self.sequence.sender.send(ComputeMessage::CopyBuffer(CopyBufferEvent { buffer: src, data })).unwrap();
-					} else {
-						render_buffers.copy_to_copy_buffer(src, buffers, context);
+			for batch in self.step_batches.iter() {
+				let steps: Vec<&ComputeStepState> =
+					batch.iter().map(|index| &self.step_states[*index]).filter(|step| step.runs[iteration]).collect();
+
+				// All the shaders in a batch are dispatched in the same compute pass. Indirect dispatches need their
+				// workgroup counts copied out first, since that can't happen during a pass.
+				let shaders: Vec<&ComputeStepState> =
+					steps.iter().copied().filter(|step| step.step.action.shader_variant().is_some()).collect();
+				for step in shaders.iter() {
+					if let (ComputeAction::RunShaderIndirect { args, offset, .. }, Some(indirect_buffer)) =
+						(&step.step.action, &step.indirect_buffer)
+					{
+						let Some(args) = buffers.gpu_buffer(*args) else {
+							panic!("Somehow tried to dispatch with indirect arguments from {}, which no longer exists", args);
+						};
+						context.command_encoder().copy_buffer_to_buffer(&args, *offset, indirect_buffer, 0, INDIRECT_ARGS_SIZE);
 					}
 				}
-				ComputeAction::RunShader { x_workgroup_count, y_workgroup_count, z_workgroup_count, .. } => {
-					if let Some(id) = step.id {
-						self.run_shader(id, x_workgroup_count, y_workgroup_count, z_workgroup_count, world, context);
-					} else {
-						panic!("Somehow got to trying to run a RunShader action step with no pipeline ID");
+				if !shaders.is_empty() {
+					let mut pass = context.command_encoder().begin_compute_pass(&ComputePassDescriptor::default());
+					for step in shaders {
+						self.run_shader(&mut pass, step, bind_groups, buffers, gpu_images);
 					}
 				}
-				ComputeAction::SwapBuffers { buffer } => {
-					self.sequence.sender.send(ComputeMessage::SwapBuffers(buffer)).unwrap();
+
+				for step in steps {
+					match step.step.action {
+						ComputeAction::CopyBuffer { src } => self.copy_back(src, buffers, context),
+						ComputeAction::RunShader { .. } | ComputeAction::RunShaderIndirect { .. } => {}
+						ComputeAction::ClearBuffer { buffer, ref range, side } => {
+							let side = if swapped.contains(&buffer) { side.other() } else { side };
+							let Some(gpu_buffer) = buffers.gpu_buffer_side(buffer, side) else {
+								panic!("Somehow tried to clear {}, which no longer exists", buffer);
+							};
+							let (offset, size) = match range {
+								Some(range) => (range.start, Some(range.end - range.start)),
+								None => (0, None),
+							};
+							context.command_encoder().clear_buffer(&gpu_buffer, offset, size);
+						}
+						ComputeAction::FillBuffer { buffer, side, .. } => {
+							let side = if swapped.contains(&buffer) { side.other() } else { side };
+							let (Some(gpu_buffer), Some(fill_buffer)) = (buffers.gpu_buffer_side(buffer, side), &step.fill_buffer)
+							else {
+								panic!("Somehow tried to fill {}, which no longer exists", buffer);
+							};
+							for offset in (0..gpu_buffer.size()).step_by(fill_buffer.size().max(4) as usize) {
+								let size = (gpu_buffer.size() - offset).min(fill_buffer.size());
+								context.command_encoder().copy_buffer_to_buffer(fill_buffer, 0, &gpu_buffer, offset, size);
+							}
+						}
+						ComputeAction::SwapBuffers { buffer } => {
+							match swapped.binary_search(&buffer) {
+								Ok(index) => {
+									swapped.remove(index);
+								}
+								Err(index) => swapped.insert(index, buffer),
+							}
+							bind_groups = if swapped.is_empty() {
+								&world.resource::<ComputeBindGroups>().0
+							} else {
+								let Some(bind_groups) = self.frame_bind_groups.get(&swapped) else {
+									panic!("Somehow swapped buffer {} without making bind groups for it", buffer);
+								};
+								bind_groups
+							};
+						}
+					}
 				}
 			}
 		}
 
+		// Let the main world know which buffers ended the frame swapped.
+		for buffer in swapped {
+			self.sequence.sender.send(ComputeMessage::SwapBuffers(buffer)).unwrap();
+		}
+
+		// Copy the condition buffers once everything else is done, so they have this frame's results.
+		if self.iterations_this_frame > 0 {
+			for buffer in self.condition_buffers.iter() {
+				self.copy_back(*buffer, buffers, context);
+			}
+		}
+
 		Ok(())
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use std::sync::mpsc::{channel, Receiver};
+
+	use super::*;
+	use crate::{
+		compute_sequence::{flatten_elements, ComputeCondition, ComputeSequenceElement},
+		test_fixtures::{task, A},
+		DEFAULT_PIPELINE_TIMEOUT,
+	};
+
+	fn node(elements: &[ComputeSequenceElement]) -> (ComputeNode, Receiver<ComputeMessage>) {
+		let mut tasks = Vec::new();
+		let mut loops = Vec::new();
+		flatten_elements(elements, &mut tasks, &mut loops);
+		let (sender, receiver) = channel();
+		let sequence = ComputeSequence {
+			sender,
+			status: default(),
+			tasks,
+			loops,
+			iteration_buffer: None,
+			wait_for_pipelines: false,
+			progress_interval: None,
+			pipeline_timeout: DEFAULT_PIPELINE_TIMEOUT,
+			control: ComputeControl::Run,
+			generation: 1,
+		};
+		(ComputeNode::new(ComputeSequenceId(0), &sequence), receiver)
+	}
+
+	// Ends the current task the same way update does, with the value copied back from A for the condition, and
+	// returns the task that ended along with how many times its loops had gone round.
+	fn end_current_task(
+		node: &mut ComputeNode, receiver: &Receiver<ComputeMessage>, condition: Option<u32>,
+	) -> (usize, Vec<u32>) {
+		if let Some(value) = condition {
+			node.condition_data.insert(A, value.to_le_bytes().to_vec());
+		}
+		let loop_iterations = node.enclosing_loop_iterations();
+		let next_task = node.following_task().unwrap();
+		node.end_task(next_task, ComputeTaskEndReason::Iterations, loop_iterations, &mut ShaderBufferRenderSet::new());
+		match receiver.try_recv() {
+			Ok(ComputeMessage::GroupDone(event)) => (event.group_finished, event.loop_iterations),
+			_ => panic!("Ending a task didn't send a ComputeTaskDoneEvent"),
+		}
+	}
+
+	#[test]
+	fn nested_loops_count_their_rounds() {
+		let (mut node, receiver) = node(&[
+			task("a"),
+			ComputeSequenceElement::repeat(2, vec![task("b"), ComputeSequenceElement::repeat(3, vec![task("c")])]),
+			task("d"),
+		]);
+		let mut ended = Vec::new();
+		while node.current_task < node.sequence.tasks.len() {
+			ended.push(end_current_task(&mut node, &receiver, None));
+		}
+		assert_eq!(
+			ended,
+			[
+				(0, vec![]),
+				(1, vec![0]),
+				(2, vec![0, 0]),
+				(2, vec![0, 1]),
+				(2, vec![0, 2]),
+				(1, vec![1]),
+				(2, vec![1, 0]),
+				(2, vec![1, 1]),
+				(2, vec![1, 2]),
+				(3, vec![]),
+			]
+		);
+	}
+
+	#[test]
+	fn loops_count_from_zero_when_reached_again() {
+		let condition = ComputeCondition::new(A, |done: u32| done != 0);
+		let (mut node, receiver) = node(&[ComputeSequenceElement::forever(vec![
+			ComputeSequenceElement::until(condition, vec![task("a")]),
+			task("b"),
+		])]);
+		let ended: Vec<_> =
+			[Some(0), Some(0), Some(1), None, Some(0)].map(|value| end_current_task(&mut node, &receiver, value)).into();
+		assert_eq!(ended, [(0, vec![0, 0]), (0, vec![0, 1]), (0, vec![0, 2]), (1, vec![0]), (0, vec![1, 0])]);
+	}
+
+	#[test]
+	fn unreadable_conditions_are_errors() {
+		let condition = ComputeCondition::new(A, |done: u32| done != 0);
+		let (mut node, _receiver) = node(&[ComputeSequenceElement::until(condition, vec![task("a")])]);
+		node.condition_data.insert(A, vec![1, 0]);
+		let error = node.following_task().unwrap_err();
+		assert!(error.starts_with("Couldn't read the condition buffer { id(1) } as the requested type"), "{}", error);
+	}
+}
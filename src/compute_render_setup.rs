@@ -6,16 +6,34 @@ use bevy::{
 		render_graph::{RenderGraph, RenderLabel},
 	},
 };
+use bevy_platform::collections::hash_set::HashSet;
 
-use super::{compute_node::ComputeNode, compute_sequence::ComputeSequence};
+use super::{
+	compute_node::ComputeNode,
+	compute_sequence::{ComputeSequenceId, ComputeSequences},
+};
 
 #[derive(Debug, Hash, PartialEq, Eq, Clone, RenderLabel)]
-pub struct ComputeLabel;
+pub struct ComputeLabel(pub ComputeSequenceId);
+
+pub fn compute_render_setup(world: &mut World, mut nodes: Local<HashSet<ComputeSequenceId>>) {
+	let mut system_state: SystemState<(ResMut<RenderGraph>, Res<ComputeSequences>)> = SystemState::new(world);
+	let (mut render_graph, sequences) = system_state.get_mut(world);
 
-pub fn compute_render_setup(world: &mut World) {
-	let mut system_state: SystemState<(ResMut<RenderGraph>, Res<ComputeSequence>)> = SystemState::new(world);
-	let (mut render_graph, sequence) = system_state.get_mut(world);
+	// Once a sequence is done, and the main world has let go of it, its node has nothing left to do.
+	nodes.retain(|id| {
+		if sequences.0.contains_key(id) {
+			return true;
+		}
+		render_graph.remove_node(ComputeLabel(*id)).ok();
+		false
+	});
 
-	render_graph.add_node(ComputeLabel, ComputeNode::new(&sequence));
-	render_graph.add_node_edge(ComputeLabel, CameraDriverLabel);
+	// Every sequence gets its own node, so they all run side by side.
+	for (id, sequence) in sequences.0.iter() {
+		if nodes.insert(*id) {
+			render_graph.add_node(ComputeLabel(*id), ComputeNode::new(*id, sequence));
+			render_graph.add_node_edge(ComputeLabel(*id), CameraDriverLabel);
+		}
+	}
 }
@@ -1,15 +1,187 @@
-use std::{num::NonZeroU32, sync::mpsc::SyncSender};
+use std::{
+	fmt::{Display, Formatter},
+	hash::{Hash, Hasher},
+	num::NonZeroU32,
+	ops::Range,
+	sync::{mpsc::Sender, Arc, Mutex},
+	time::Duration,
+};
 
-use bevy::{prelude::*, render::extract_resource::ExtractResource};
+use bevy::{
+	prelude::*,
+	render::{
+		extract_resource::ExtractResource,
+		render_asset::RenderAssets,
+		render_resource::{
+			encase::{self, private::CreateFrom},
+			ShaderDefVal, ShaderType,
+		},
+		texture::GpuImage,
+	},
+};
+use bevy_platform::collections::hash_map::HashMap;
+use serde::Deserialize;
+
+use super::{compute_data_transmission::ComputeMessage, ComputeSequenceStatus};
+use crate::shader_buffer_set::{ShaderBufferHandle, ShaderBufferSet};
+
+/// Identifies a compute sequence. You pick the id when you start a sequence with a [StartComputeEvent](crate::StartComputeEvent), and every event to or from that sequence carries it. Any number of sequences can run at the same time, as long as they each have a different id.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct ComputeSequenceId(pub u32);
 
-use super::compute_data_transmission::ComputeMessage;
-use crate::shader_buffer_set::ShaderBufferHandle;
+impl Display for ComputeSequenceId {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result { write!(f, "{}", self.0) }
+}
 
+// All the sequences that are currently running, keyed by their id.
 #[derive(Resource, Clone, ExtractResource)]
+pub(crate) struct ComputeSequences(pub HashMap<ComputeSequenceId, ComputeSequence>);
+
+#[derive(Clone)]
 pub(crate) struct ComputeSequence {
-	pub sender: SyncSender<ComputeMessage>,
+	pub sender: Sender<ComputeMessage>,
+	// The render world overwrites this with the sequence's latest status every frame, and the main world takes it
+	// from there. It's kept out of the channel, since only the latest one matters. Every sequence that's started gets
+	// a new one, so a status from a sequence that's been replaced never ends up here.
+	pub status: Arc<Mutex<Option<ComputeSequenceStatus>>>,
+	// The tasks from every element of the sequence, in order, with the loops kept separately.
 	pub tasks: Vec<ComputeTask>,
+	pub loops: Vec<ComputeLoop>,
 	pub iteration_buffer: Option<ShaderBufferHandle>,
+	pub wait_for_pipelines: bool,
+	pub progress_interval: Option<Duration>,
+	pub pipeline_timeout: Duration,
+	pub control: ComputeControl,
+	// Incremented every time a sequence is started, so that the render world can tell when a sequence has been
+	// replaced by a new one with the same id.
+	pub generation: u64,
+}
+
+// The state the main world has requested for a running sequence. The compute node only checks this between
+// iterations, so a request never interrupts an iteration part way through.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ComputeControl {
+	Run,
+	Pause,
+	Stop,
+}
+
+/// An element of a compute sequence. Most elements are just [ComputeTask]s, but tasks can also be grouped into loops, so that multi-stage algorithms don't have to be unrolled by hand. Loops can be nested inside each other.
+///
+/// Every task in the sequence has an index, counting through the tasks in the order they appear, including the ones in loops. So the first task in a loop has the index after the last task before the loop, however many times the loop goes round. That's the index reported by [ComputeTaskDoneEvent](crate::ComputeTaskDoneEvent), along with how many times each loop the task is in has gone round, so tasks that run more than once can still be told apart.
+#[derive(Clone)]
+pub enum ComputeSequenceElement {
+	/// A single task.
+	Task(ComputeTask),
+
+	/// Runs the elements this many times over before moving on.
+	Repeat {
+		/// The number of times to run the elements.
+		count: NonZeroU32,
+
+		/// The elements to run.
+		elements: Vec<ComputeSequenceElement>,
+	},
+
+	/// Runs the elements over and over again, forever. Wrapping every element in one of these loops the whole sequence. It will only end if it's stopped, or if a task's [condition](ComputeTask::condition) moves on to a task outside the loop.
+	Loop(Vec<ComputeSequenceElement>),
+
+	/// Runs the elements over and over again until the condition is satisfied. The condition is checked whenever the last task in the loop ends, using the last data copied back from that task, and if it didn't get to run any iterations, the loop goes round again. Just like a task's condition, it moves on to the task after the loop, unless it has a [next_task](ComputeCondition::next_task).
+	LoopUntil {
+		/// The condition that ends the loop.
+		condition: ComputeCondition,
+
+		/// The elements to run.
+		elements: Vec<ComputeSequenceElement>,
+	},
+}
+
+impl From<ComputeTask> for ComputeSequenceElement {
+	fn from(task: ComputeTask) -> Self { Self::Task(task) }
+}
+
+impl ComputeSequenceElement {
+	/// Make a [Repeat](ComputeSequenceElement::Repeat) element. Panics if the count is zero, or there are no elements.
+	pub fn repeat(count: u32, elements: Vec<ComputeSequenceElement>) -> Self {
+		let Some(count) = NonZeroU32::new(count) else {
+			panic!("Tried to repeat elements zero times");
+		};
+		Self::Repeat { count, elements: Self::loop_elements(elements) }
+	}
+
+	/// Make a [Loop](ComputeSequenceElement::Loop) element. Panics if there are no elements.
+	pub fn forever(elements: Vec<ComputeSequenceElement>) -> Self { Self::Loop(Self::loop_elements(elements)) }
+
+	/// Make a [LoopUntil](ComputeSequenceElement::LoopUntil) element. Panics if there are no elements.
+	pub fn until(condition: ComputeCondition, elements: Vec<ComputeSequenceElement>) -> Self {
+		Self::LoopUntil { condition, elements: Self::loop_elements(elements) }
+	}
+
+	fn loop_elements(elements: Vec<ComputeSequenceElement>) -> Vec<ComputeSequenceElement> {
+		if elements.is_empty() {
+			panic!("Tried to make a loop with nothing in it");
+		}
+		elements
+	}
+
+	// Makes sure every task has something to do, and every loop has something in it.
+	pub(crate) fn validate(&self) {
+		match self {
+			ComputeSequenceElement::Task(task) => {
+				if task.steps.is_empty() {
+					panic!("Tried to add task {} to a sequence, but it doesn't have any steps", task.name());
+				}
+			}
+			ComputeSequenceElement::Repeat { elements, .. }
+			| ComputeSequenceElement::Loop(elements)
+			| ComputeSequenceElement::LoopUntil { elements, .. } => {
+				if elements.is_empty() {
+					panic!("Tried to add a loop with nothing in it to a sequence");
+				}
+				elements.iter().for_each(Self::validate);
+			}
+		}
+	}
+}
+
+// A loop over a range of a sequence's tasks.
+#[derive(Clone)]
+pub(crate) struct ComputeLoop {
+	pub start: usize,
+	pub end: usize,
+	pub kind: ComputeLoopKind,
+}
+
+#[derive(Clone)]
+pub(crate) enum ComputeLoopKind {
+	Repeat(NonZeroU32),
+	Forever,
+	Until(ComputeCondition),
+}
+
+// Flattens the elements of a sequence into a list of tasks and the loops over them. Inner loops always come before the
+// loops they're in, and loops with no tasks in them are left out, since there would be nothing for them to run.
+pub(crate) fn flatten_elements(
+	elements: &[ComputeSequenceElement], tasks: &mut Vec<ComputeTask>, loops: &mut Vec<ComputeLoop>,
+) {
+	for element in elements {
+		let (kind, elements) = match element {
+			ComputeSequenceElement::Task(task) => {
+				tasks.push(task.clone());
+				continue;
+			}
+			ComputeSequenceElement::Repeat { count, elements } => (ComputeLoopKind::Repeat(*count), elements),
+			ComputeSequenceElement::Loop(elements) => (ComputeLoopKind::Forever, elements),
+			ComputeSequenceElement::LoopUntil { condition, elements } => {
+				(ComputeLoopKind::Until(condition.clone()), elements)
+			}
+		};
+		let start = tasks.len();
+		flatten_elements(elements, tasks, loops);
+		if tasks.len() > start {
+			loops.push(ComputeLoop { start, end: tasks.len(), kind });
+		}
+	}
 }
 
 /// This describes a compute shader task, which is a set of things it should do every tick, for some number of iterations.
@@ -18,23 +190,596 @@ pub struct ComputeTask {
 	/// The optional label is sent back in the [ComputeTaskDoneEvent](crate::ComputeTaskDoneEvent) when this task is completed. It makes it easier to identify which task was completed.
 	pub label: Option<String>,
 
-	/// The number of times to run this task before considering it done. If this isn't provided, it will run forever.
+	/// The number of times to run this task before considering it done. If neither this nor a [duration](ComputeTask::duration) is provided, it will run forever.
 	pub iterations: Option<NonZeroU32>,
 
+	/// How long to run this task before considering it done, measured from when the task started, including any time spent waiting for its pipelines, but not time spent paused. This is checked once per frame, before that frame's iterations, so the task can run for up to a frame longer than this. If both this and [iterations](ComputeTask::iterations) are provided, the task ends as soon as either one is reached.
+	pub duration: Option<Duration>,
+
+	/// The number of iterations to run each frame. If this isn't provided, it runs one iteration per frame. Raising it lets short iterations get through a long task without being held back by the framerate, since all of a frame's iterations are recorded into the same command encoder. The [iteration buffer](crate::StartComputeEvent::iteration_buffer), step schedules and buffer swaps are all still handled per iteration, but a [condition](ComputeTask::condition) is only checked once per frame, after the last iteration, and a [CopyBuffer](ComputeAction::CopyBuffer) step runs at most once per frame.
+	pub iterations_per_frame: Option<NonZeroU32>,
+
 	/// The set of steps to execute on each iteration.
 	pub steps: Vec<ComputeStep>,
+
+	/// How to order the steps within each iteration. See [ComputeStepOrder] for the options.
+	pub step_order: ComputeStepOrder,
+
+	/// An optional condition that can end this task early, based on data read back from the GPU. If this is provided along with [iterations](ComputeTask::iterations) or a [duration](ComputeTask::duration), the task ends as soon as any of them says it's done.
+	pub condition: Option<ComputeCondition>,
+}
+
+/// These functions build a task up one piece at a time, which is usually easier to read than the struct itself. For example:
+///
+/// ```Rust
+/// ComputeTask::new("Update")
+///     .forever()
+///     .run("shaders/game_of_life.wgsl", "update", DispatchSize::PerTexel(image))
+///     .every(10.hz())
+///     .swap(image)
+///     .every(10.hz())
+/// ```
+///
+/// The functions that add steps, like [run](ComputeTask::run) and [swap](ComputeTask::swap), add them with default settings, and the functions that change a step's settings, like [every](ComputeTask::every) and [shader_def](ComputeTask::shader_def), change the last step that was added. Anything that doesn't make sense, like zero iterations or changing the shader defs of a step that doesn't run a shader, panics straight away.
+impl ComputeTask {
+	/// Start building a task with the given label. It has no steps, and runs forever, until you say otherwise.
+	pub fn new(label: impl Into<String>) -> Self {
+		Self {
+			label: Some(label.into()),
+			iterations: None,
+			duration: None,
+			iterations_per_frame: None,
+			steps: Vec::new(),
+			step_order: ComputeStepOrder::InOrder,
+			condition: None,
+		}
+	}
+
+	/// Run the task this many times before moving on. Panics if it's zero.
+	pub fn iterations(mut self, iterations: u32) -> Self {
+		let Some(iterations) = NonZeroU32::new(iterations) else {
+			panic!("Tried to give task {} zero iterations", self.name());
+		};
+		self.iterations = Some(iterations);
+		self
+	}
+
+	/// Run the task for this long before moving on. Panics if it's zero.
+	pub fn duration(mut self, duration: Duration) -> Self {
+		if duration.is_zero() {
+			panic!("Tried to give task {} a duration of zero", self.name());
+		}
+		self.duration = Some(duration);
+		self
+	}
+
+	/// Run the task forever, or until its condition is satisfied. This is what tasks do by default, so this just makes it clear, and undoes any [iterations](ComputeTask::iterations) or [duration](ComputeTask::duration) given so far.
+	pub fn forever(mut self) -> Self {
+		self.iterations = None;
+		self.duration = None;
+		self
+	}
+
+	/// Run this many iterations each frame. Panics if it's zero.
+	pub fn iterations_per_frame(mut self, iterations: u32) -> Self {
+		let Some(iterations) = NonZeroU32::new(iterations) else {
+			panic!("Tried to run task {} zero iterations per frame", self.name());
+		};
+		self.iterations_per_frame = Some(iterations);
+		self
+	}
+
+	/// Set how the task's steps are ordered.
+	pub fn step_order(mut self, step_order: ComputeStepOrder) -> Self {
+		self.step_order = step_order;
+		self
+	}
+
+	/// End the task when the condition is satisfied.
+	pub fn until(mut self, condition: ComputeCondition) -> Self {
+		self.condition = Some(condition);
+		self
+	}
+
+	/// Add a step.
+	pub fn step(mut self, step: ComputeStep) -> Self {
+		self.steps.push(step);
+		self
+	}
+
+	/// Add a step that runs a shader. See [ComputeStep::run].
+	pub fn run(self, shader: impl Into<String>, entry_point: impl Into<String>, dispatch: DispatchSize) -> Self {
+		self.step(ComputeStep::run(shader, entry_point, dispatch))
+	}
+
+	/// Add a step that runs a shader with its workgroup counts read from a buffer. See [ComputeStep::run_indirect].
+	pub fn run_indirect(
+		self, shader: impl Into<String>, entry_point: impl Into<String>, args: ShaderBufferHandle, offset: u64,
+	) -> Self {
+		self.step(ComputeStep::run_indirect(shader, entry_point, args, offset))
+	}
+
+	/// Add a step that copies a buffer back to the CPU. See [ComputeStep::copy].
+	pub fn copy(self, buffer: ShaderBufferHandle) -> Self { self.step(ComputeStep::copy(buffer)) }
+
+	/// Add a step that swaps a double buffer. See [ComputeStep::swap].
+	pub fn swap(self, buffer: ShaderBufferHandle) -> Self { self.step(ComputeStep::swap(buffer)) }
+
+	/// Add a step that sets a whole buffer to zero. See [ComputeStep::clear].
+	pub fn clear(self, buffer: ShaderBufferHandle) -> Self { self.step(ComputeStep::clear(buffer)) }
+
+	/// Add a step that sets a range of bytes in a buffer to zero. See [ComputeStep::clear_range].
+	pub fn clear_range(self, buffer: ShaderBufferHandle, range: Range<u64>) -> Self {
+		self.step(ComputeStep::clear_range(buffer, range))
+	}
+
+	/// Add a step that fills a buffer with a repeating value. See [ComputeStep::fill].
+	pub fn fill(self, buffer: ShaderBufferHandle, value: u32) -> Self { self.step(ComputeStep::fill(buffer, value)) }
+
+	/// Set the schedule of the last step. See [ComputeStep::schedule].
+	pub fn schedule(self, schedule: ComputeSchedule) -> Self {
+		self.last_step("schedule", |step| step.schedule(schedule))
+	}
+
+	/// Set how often the last step runs. See [ComputeStep::every].
+	pub fn every(self, interval: impl StepInterval) -> Self { self.last_step("every", |step| step.every(interval)) }
+
+	/// Set which of a double buffer's buffers the last step clears or fills. See [ComputeStep::side].
+	pub fn side(self, side: BufferSide) -> Self { self.last_step("side", |step| step.side(side)) }
+
+	/// Add to the buffers the last step reads. See [ComputeStep::reads].
+	pub fn reads(self, buffers: impl IntoIterator<Item = ShaderBufferHandle>) -> Self {
+		self.last_step("reads", |step| step.reads(buffers))
+	}
+
+	/// Add to the buffers the last step writes. See [ComputeStep::writes].
+	pub fn writes(self, buffers: impl IntoIterator<Item = ShaderBufferHandle>) -> Self {
+		self.last_step("writes", |step| step.writes(buffers))
+	}
+
+	/// Add a shader def to the last step. See [ComputeStep::shader_def].
+	pub fn shader_def(self, shader_def: impl Into<ShaderDefVal>) -> Self {
+		self.last_step("shader_def", |step| step.shader_def(shader_def))
+	}
+
+	/// Set an override constant for the last step. See [ComputeStep::constant].
+	pub fn constant(self, name: impl Into<String>, value: ShaderConstant) -> Self {
+		self.last_step("constant", |step| step.constant(name, value))
+	}
+
+	/// Set the push constants for the last step. See [ComputeStep::push_constants].
+	pub fn push_constants(self, push_constants: impl Into<Vec<u8>>) -> Self {
+		self.last_step("push_constants", |step| step.push_constants(push_constants))
+	}
+
+	/// Set the bind groups the last step uses. See [ComputeStep::bind_groups].
+	pub fn bind_groups(self, bind_groups: impl Into<Vec<u32>>) -> Self {
+		self.last_step("bind_groups", |step| step.bind_groups(bind_groups))
+	}
+
+	fn last_step(mut self, function: &str, change: impl FnOnce(ComputeStep) -> ComputeStep) -> Self {
+		let Some(step) = self.steps.pop() else {
+			panic!(
+				"Tried to use {} on task {}, which changes the last step, but there aren't any steps yet",
+				function,
+				self.name()
+			);
+		};
+		self.steps.push(change(step));
+		self
+	}
+
+	// How the task is referred to in panic messages.
+	pub(crate) fn name(&self) -> String {
+		match &self.label {
+			Some(label) => format!("\"{}\"", label),
+			None => "with no label".to_owned(),
+		}
+	}
+}
+
+/// A predicate on the raw bytes of a buffer, as used by a [ComputeCondition]. It returns an error if it can't make sense of the bytes, which fails the sequence.
+pub type ComputePredicate = Arc<dyn Fn(&[u8]) -> Result<bool, String> + Send + Sync>;
+
+/// A condition that ends a [ComputeTask] based on the contents of a GPU buffer. This is what you want for iterative algorithms that should keep going until they've converged, rather than for a fixed number of iterations. For instance, a solver could write its residual into a buffer, and the condition could check whether it's dropped below some threshold.
+///
+/// At the end of every frame's iterations, the buffer is copied back to the CPU without waiting on the GPU, and once the data arrives, usually a frame or two later, it's passed to the predicate. If the predicate returns true, the task is done. This means the task keeps running for a few iterations after the data first satisfies the predicate, so the shaders should leave the buffer alone once they've set it. A task that's done waits for its last copies to come back before it ends, so the conditions of any loops it ends see its final results. Reading data back from the GPU every frame isn't free, so the buffer should be small, like a single `u32` flag or `f32` residual.
+///
+/// The buffer works just like one copied with [CopyBuffer](ComputeAction::CopyBuffer), so it must be a single storage buffer, and it can't also be copied by a [CopyBuffer](ComputeAction::CopyBuffer) step in the same task.
+#[derive(Clone)]
+pub struct ComputeCondition {
+	/// The buffer to read.
+	pub buffer: ShaderBufferHandle,
+
+	/// This is given the raw bytes of the buffer, and returns whether the task is done. [ComputeCondition::new] can build this for you from a predicate that takes a [ShaderType], which is usually more convenient.
+	pub predicate: ComputePredicate,
+
+	/// The size the buffer has to be for the predicate to read it, in bytes, if it needs a particular size. A sequence whose condition buffer is a different size is turned away before it starts. [ComputeCondition::new] sets this to the size of its type.
+	pub size: Option<u64>,
+
+	/// The label of the task to move on to when the predicate is satisfied. If this isn't provided, it moves on to the next task in the list, just as if it had run out of iterations. This can point to any task in the sequence, including earlier ones or this one, which lets you build a state machine out of the tasks.
+	pub next_task: Option<String>,
+}
+
+impl ComputeCondition {
+	/// Create a condition that reads the buffer as a `T`, and passes that to the predicate. The buffer must be the same size as a `T`, which is checked before the sequence starts. The condition will move on to the next task in the list when it's satisfied, but you can change that by setting [next_task](ComputeCondition::next_task).
+	pub fn new<T: ShaderType + CreateFrom>(
+		buffer: ShaderBufferHandle, predicate: impl Fn(T) -> bool + Send + Sync + 'static,
+	) -> Self {
+		Self {
+			buffer,
+			predicate: Arc::new(move |data| {
+				let value = encase::StorageBuffer::new(data)
+					.create::<T>()
+					.map_err(|e| format!("Couldn't read the condition buffer {} as the requested type: {}", buffer, e))?;
+				Ok(predicate(value))
+			}),
+			size: Some(T::min_size().get()),
+			next_task: None,
+		}
+	}
+}
+
+/// Describes how the [ComputeStep]s of a [ComputeTask] are ordered within each iteration.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug, Deserialize)]
+pub enum ComputeStepOrder {
+	/// Run the steps in the order they're given, each shader in its own compute pass.
+	#[default]
+	InOrder,
+
+	/// Order the steps by the buffers they declare they [read](ComputeStep::reads) and [write](ComputeStep::writes). Any two steps that use the same buffer, where at least one of them writes it, run in the order they're given, so a step that reads a buffer sees what earlier steps wrote, and a later step that writes it, like a [ClearBuffer](ComputeAction::ClearBuffer) that resets it after it's been read, waits until it has been. Steps that don't depend on each other are put in the same batch, and all the shaders in a batch are dispatched in a single compute pass, which saves a lot of overhead in long pipelines of small dispatches.
+	///
+	/// Some actions use buffers without them having to be declared. A [CopyBuffer](ComputeAction::CopyBuffer) reads the buffer it copies, a [RunShaderIndirect](ComputeAction::RunShaderIndirect) reads its arguments, and a [ClearBuffer](ComputeAction::ClearBuffer) or [FillBuffer](ComputeAction::FillBuffer) writes the buffer it sets. A [SwapBuffers](ComputeAction::SwapBuffers) runs after every step that reads or writes the buffer it swaps, so steps that should use the buffer after it's been swapped belong in the next task, or in the next iteration.
+	///
+	/// Any buffer a shader uses but doesn't declare isn't taken into account, so the order of any steps that share it is up to chance. Since swaps run after everything else that uses their buffer, the accesses can depend on each other in a cycle, like a swap of one buffer that's given before a step that reads it, along with a buffer the swap step writes. If they do, the sequence fails when the task starts.
+	ByBufferAccess,
 }
 
 /// A compute step is one action to do during a compute task.
 #[derive(Clone)]
 pub struct ComputeStep {
-	/// The max frequency allows you to make it so a step won't run on every iteration. If provided, then this is the maximum number of times it will run per second. For instance, if a max frequency of 30 is given, then it will be at least 1000 / 30 = 16.67 ms between each run. When it's going through the steps, if it hasn't been at least 16.67 ms since the last time it ran, it won't run this time.
-	///
-	/// Compute shaders can sometimes be rather expensive, and use a lot of GPU resources. Not running them every frame can sometimes be a significant performance improvement. If you have a long-running compute task which is providing a real-time visualization, it can be a useful optimization to say that the steps that update the visuals run at a lower frequency. In the Game of Life example, if the game is running at full speed on a 120 Hz monitor, it can be very difficult to see what's going down, so the example slows it down to 10 Hz.
-	pub max_frequency: Option<NonZeroU32>,
+	/// The schedule decides which of the task's iterations this step runs on. See [ComputeSchedule] for the options.
+	pub schedule: ComputeSchedule,
 
 	/// This is the actual action to perform.
 	pub action: ComputeAction,
+
+	/// The buffers this step reads. These are only used to order the steps when the task's [step_order](ComputeTask::step_order) is [ByBufferAccess](ComputeStepOrder::ByBufferAccess).
+	pub reads: Vec<ShaderBufferHandle>,
+
+	/// The buffers this step writes, including any it reads as well. These are only used to order the steps when the task's [step_order](ComputeTask::step_order) is [ByBufferAccess](ComputeStepOrder::ByBufferAccess).
+	pub writes: Vec<ShaderBufferHandle>,
+}
+
+/// These functions build a step up one setting at a time. Steps start out running on every iteration, with no declared buffer accesses, and shaders start out with no shader defs, constants or push constants, using every bind group. The settings that only apply to shaders panic if the step doesn't run one.
+impl ComputeStep {
+	/// Make a step that performs an action on every iteration.
+	pub fn new(action: ComputeAction) -> Self {
+		Self { schedule: ComputeSchedule::Always, action, reads: Vec::new(), writes: Vec::new() }
+	}
+
+	/// Make a step that runs an entry point in a shader. See [RunShader](ComputeAction::RunShader).
+	pub fn run(shader: impl Into<String>, entry_point: impl Into<String>, dispatch: DispatchSize) -> Self {
+		Self::new(ComputeAction::RunShader {
+			shader: shader.into(),
+			entry_point: entry_point.into(),
+			shader_defs: Vec::new(),
+			constants: Vec::new(),
+			push_constants: Vec::new(),
+			bind_groups: None,
+			dispatch,
+		})
+	}
+
+	/// Make a step that runs an entry point in a shader, with its workgroup counts read from a buffer. Panics if the offset isn't a multiple of 4. See [RunShaderIndirect](ComputeAction::RunShaderIndirect).
+	pub fn run_indirect(
+		shader: impl Into<String>, entry_point: impl Into<String>, args: ShaderBufferHandle, offset: u64,
+	) -> Self {
+		if !offset.is_multiple_of(4) {
+			panic!("Tried to read indirect dispatch arguments from offset {}, which isn't a multiple of 4", offset);
+		}
+		Self::new(ComputeAction::RunShaderIndirect {
+			shader: shader.into(),
+			entry_point: entry_point.into(),
+			shader_defs: Vec::new(),
+			constants: Vec::new(),
+			push_constants: Vec::new(),
+			bind_groups: None,
+			args,
+			offset,
+		})
+	}
+
+	/// Make a step that copies a buffer back to the CPU. See [CopyBuffer](ComputeAction::CopyBuffer).
+	pub fn copy(buffer: ShaderBufferHandle) -> Self { Self::new(ComputeAction::CopyBuffer { src: buffer }) }
+
+	/// Make a step that swaps a double buffer. See [SwapBuffers](ComputeAction::SwapBuffers).
+	pub fn swap(buffer: ShaderBufferHandle) -> Self { Self::new(ComputeAction::SwapBuffers { buffer }) }
+
+	/// Make a step that sets a whole buffer to zero. See [ClearBuffer](ComputeAction::ClearBuffer).
+	pub fn clear(buffer: ShaderBufferHandle) -> Self {
+		Self::new(ComputeAction::ClearBuffer { buffer, range: None, side: BufferSide::Front })
+	}
+
+	/// Make a step that sets a range of bytes in a buffer to zero. Panics if either end of the range isn't a multiple of 4, or it's empty. See [ClearBuffer](ComputeAction::ClearBuffer).
+	pub fn clear_range(buffer: ShaderBufferHandle, range: Range<u64>) -> Self {
+		if !range.start.is_multiple_of(4) || !range.end.is_multiple_of(4) {
+			panic!("Tried to clear bytes {:?} of {}, but both ends have to be multiples of 4", range, buffer);
+		}
+		if range.is_empty() {
+			panic!("Tried to clear bytes {:?} of {}, which is an empty range", range, buffer);
+		}
+		Self::new(ComputeAction::ClearBuffer { buffer, range: Some(range), side: BufferSide::Front })
+	}
+
+	/// Make a step that fills a buffer with a repeating value. See [FillBuffer](ComputeAction::FillBuffer).
+	pub fn fill(buffer: ShaderBufferHandle, value: u32) -> Self {
+		Self::new(ComputeAction::FillBuffer { buffer, value, side: BufferSide::Front })
+	}
+
+	/// Set which of a double buffer's buffers the step clears or fills. Panics if the step doesn't clear or fill a buffer.
+	pub fn side(mut self, side: BufferSide) -> Self {
+		match &mut self.action {
+			ComputeAction::ClearBuffer { side: action_side, .. } | ComputeAction::FillBuffer { side: action_side, .. } => {
+				*action_side = side;
+			}
+			_ => panic!("Tried to use side on a step that doesn't clear or fill a buffer"),
+		}
+		self
+	}
+
+	/// Set which iterations the step runs on.
+	pub fn schedule(mut self, schedule: ComputeSchedule) -> Self {
+		self.schedule = schedule;
+		self
+	}
+
+	/// Set how often the step runs, either every so many iterations, like `every(3)`, or at most so many times a second, like `every(10.hz())`. Panics if it's zero.
+	pub fn every(self, interval: impl StepInterval) -> Self { self.schedule(interval.schedule()) }
+
+	/// Add to the buffers the step reads.
+	pub fn reads(mut self, buffers: impl IntoIterator<Item = ShaderBufferHandle>) -> Self {
+		self.reads.extend(buffers);
+		self
+	}
+
+	/// Add to the buffers the step writes.
+	pub fn writes(mut self, buffers: impl IntoIterator<Item = ShaderBufferHandle>) -> Self {
+		self.writes.extend(buffers);
+		self
+	}
+
+	/// Add a shader def to compile the step's shader with.
+	pub fn shader_def(mut self, shader_def: impl Into<ShaderDefVal>) -> Self {
+		self.shader_settings("shader_def").shader_defs.push(shader_def.into());
+		self
+	}
+
+	/// Set an override constant for the step's shader. Panics if it's already been set.
+	pub fn constant(mut self, name: impl Into<String>, value: ShaderConstant) -> Self {
+		let name = name.into();
+		let constants = self.shader_settings("constant").constants;
+		if constants.iter().any(|(existing, _)| *existing == name) {
+			panic!("Tried to set the override constant {} twice", name);
+		}
+		constants.push((name, value));
+		self
+	}
+
+	/// Set the push constants for the step's shader. Panics if the length isn't a multiple of 4.
+	pub fn push_constants(mut self, push_constants: impl Into<Vec<u8>>) -> Self {
+		let push_constants = push_constants.into();
+		if !push_constants.len().is_multiple_of(4) {
+			panic!("Tried to set {} bytes of push constants, which isn't a multiple of 4", push_constants.len());
+		}
+		*self.shader_settings("push_constants").push_constants = push_constants;
+		self
+	}
+
+	/// Set which of the [ShaderBufferSet]'s bind groups the step's shader uses.
+	pub fn bind_groups(mut self, bind_groups: impl Into<Vec<u32>>) -> Self {
+		*self.shader_settings("bind_groups").bind_groups = Some(bind_groups.into());
+		self
+	}
+
+	fn shader_settings(&mut self, function: &str) -> ShaderSettings<'_> {
+		match &mut self.action {
+			ComputeAction::RunShader { shader_defs, constants, push_constants, bind_groups, .. }
+			| ComputeAction::RunShaderIndirect { shader_defs, constants, push_constants, bind_groups, .. } => {
+				ShaderSettings { shader_defs, constants, push_constants, bind_groups }
+			}
+			_ => panic!("Tried to use {} on a step that doesn't run a shader", function),
+		}
+	}
+}
+
+// The settings a step only has if it runs a shader.
+struct ShaderSettings<'a> {
+	shader_defs: &'a mut Vec<ShaderDefVal>,
+	constants: &'a mut Vec<(String, ShaderConstant)>,
+	push_constants: &'a mut Vec<u8>,
+	bind_groups: &'a mut Option<Vec<u32>>,
+}
+
+/// How often a step runs, as given to [every](ComputeStep::every). A `u32` runs it on every Nth iteration, like [Every](ComputeSchedule::Every), and a [Frequency] runs it at most that many times a second, like [MaxFrequency](ComputeSchedule::MaxFrequency).
+pub trait StepInterval {
+	/// The schedule that runs a step this often.
+	fn schedule(self) -> ComputeSchedule;
+}
+
+impl StepInterval for u32 {
+	fn schedule(self) -> ComputeSchedule {
+		let Some(interval) = NonZeroU32::new(self) else {
+			panic!("Tried to run a step every 0 iterations");
+		};
+		ComputeSchedule::Every(interval)
+	}
+}
+
+impl StepInterval for Frequency {
+	fn schedule(self) -> ComputeSchedule { ComputeSchedule::MaxFrequency(self.0) }
+}
+
+/// A number of times per second. The easiest way to make one is with [hz](Hz::hz), as in `10.hz()`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Frequency(pub NonZeroU32);
+
+/// Lets a number be written as a [Frequency], as in `10.hz()`.
+pub trait Hz {
+	/// This many times per second. Panics if it's zero.
+	fn hz(self) -> Frequency;
+}
+
+impl Hz for u32 {
+	fn hz(self) -> Frequency {
+		let Some(frequency) = NonZeroU32::new(self) else {
+			panic!("Tried to make a frequency of 0 Hz");
+		};
+		Frequency(frequency)
+	}
+}
+
+/// Describes which iterations of a [ComputeTask] a [ComputeStep] runs on.
+///
+/// Compute shaders can sometimes be rather expensive, and use a lot of GPU resources. Not running them every iteration can sometimes be a significant performance improvement. If you have a long-running compute task which is providing a real-time visualization, it can be a useful optimization to say that the steps that update the visuals run less often.
+///
+/// Every variant other than [MaxFrequency](ComputeSchedule::MaxFrequency) is based only on the task's iteration count, so the same sequence will always run the same steps on the same iterations, no matter how fast the machine is.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug, Deserialize)]
+pub enum ComputeSchedule {
+	/// Run on every iteration.
+	#[default]
+	Always,
+
+	/// Run on every Nth iteration, starting with the first. So `Every(3)` runs on iterations 0, 3, 6, and so on.
+	Every(NonZeroU32),
+
+	/// Run only on the first iteration of the task.
+	FirstIteration,
+
+	/// Run only on the last iteration of the task. This can only be used on tasks with a fixed number of [iterations](ComputeTask::iterations), and if a [condition](ComputeTask::condition) or [duration](ComputeTask::duration) ends the task early, it won't run at all.
+	LastIteration,
+
+	/// Run at most this many times per second. For instance, if a max frequency of 30 is given, then it will be at least 1000 / 30 = 16.67 ms between each run. When it's going through the steps, if it hasn't been at least 16.67 ms since the last time it ran, it won't run this time. This is based on the wall clock, so which iterations it runs on depends on how fast things are going. In the Game of Life example, if the game is running at full speed on a 120 Hz monitor, it can be very difficult to see what's going down, so the example slows it down to 10 Hz.
+	MaxFrequency(NonZeroU32),
+}
+
+/// Describes how many workgroups a [RunShader](ComputeAction::RunShader) step dispatches.
+///
+/// Other than [Workgroups](DispatchSize::Workgroups), these all work out the number of workgroups from the size of a buffer and the `@workgroup_size` declared on the shader's entry point, which is read by parsing the shader. The counts are rounded up, so every texel or element gets an invocation even when the size isn't a multiple of the workgroup size. That means some invocations may be past the end of the data, and the shader should check for that. The counts are worked out again every time the step runs, so they stay correct if the buffer is replaced.
+#[derive(Clone)]
+pub enum DispatchSize {
+	/// Dispatch exactly this many workgroups, in the X, Y and Z dimensions respectively.
+	Workgroups(u32, u32, u32),
+
+	/// Dispatch one invocation per texel of this texture buffer, with its width, height and depth along the X, Y and Z dimensions. If it's a double buffer, the front buffer's size is used, which is always the same as the back buffer's.
+	PerTexel(ShaderBufferHandle),
+
+	/// Dispatch one invocation per element of this storage or uniform buffer, along the X dimension. If it's a double buffer, the front buffer's size is used, which is always the same as the back buffer's.
+	PerElement {
+		/// The buffer to dispatch over.
+		buffer: ShaderBufferHandle,
+
+		/// The size of each element in bytes. Keep in mind that this has to account for the padding in the array on the shader side, so for instance the stride of an array of `vec3<f32>` is 16, not 12.
+		stride: NonZeroU32,
+	},
+}
+
+impl DispatchSize {
+	pub(crate) fn needs_workgroup_size(&self) -> bool { !matches!(self, DispatchSize::Workgroups(..)) }
+
+	pub(crate) fn workgroup_counts(
+		&self, workgroup_size: [u32; 3], buffers: &ShaderBufferSet, gpu_images: &RenderAssets<GpuImage>,
+	) -> (u32, u32, u32) {
+		let invocations = match *self {
+			DispatchSize::Workgroups(x, y, z) => return (x, y, z),
+			DispatchSize::PerTexel(texture) => {
+				let Some(size) = buffers.texture_size(texture, gpu_images) else {
+					panic!(
+						"Somehow tried to dispatch one invocation per texel of {}, which isn't a loaded texture buffer",
+						texture
+					);
+				};
+				[size.width, size.height, size.depth_or_array_layers]
+			}
+			DispatchSize::PerElement { buffer, stride } => {
+				let Some(gpu_buffer) = buffers.gpu_buffer(buffer) else {
+					panic!(
+						"Somehow tried to dispatch one invocation per element of {}, which isn't a storage or uniform buffer",
+						buffer
+					);
+				};
+				[(gpu_buffer.size() / stride.get() as u64) as u32, 1, 1]
+			}
+		};
+		(
+			invocations[0].div_ceil(workgroup_size[0]),
+			invocations[1].div_ceil(workgroup_size[1]),
+			invocations[2].div_ceil(workgroup_size[2]),
+		)
+	}
+}
+
+/// A value for a pipeline-overridable constant, which is declared in WGSL with `override`. It must match the type the shader declares the constant with.
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub enum ShaderConstant {
+	/// A `bool` constant.
+	Bool(bool),
+
+	/// An `f32` constant.
+	F32(f32),
+
+	/// An `i32` constant.
+	I32(i32),
+
+	/// A `u32` constant.
+	U32(u32),
+}
+
+impl ShaderConstant {
+	// Naga takes every constant as an f64, and converts it to the type the shader declares.
+	pub(crate) fn as_f64(self) -> f64 {
+		match self {
+			ShaderConstant::Bool(value) => value as u8 as f64,
+			ShaderConstant::F32(value) => value as f64,
+			ShaderConstant::I32(value) => value as f64,
+			ShaderConstant::U32(value) => value as f64,
+		}
+	}
+}
+
+// Constants are compared bit for bit, so they can be used to tell pipelines apart.
+impl PartialEq for ShaderConstant {
+	fn eq(&self, other: &Self) -> bool {
+		match (self, other) {
+			(ShaderConstant::Bool(a), ShaderConstant::Bool(b)) => a == b,
+			(ShaderConstant::F32(a), ShaderConstant::F32(b)) => a.to_bits() == b.to_bits(),
+			(ShaderConstant::I32(a), ShaderConstant::I32(b)) => a == b,
+			(ShaderConstant::U32(a), ShaderConstant::U32(b)) => a == b,
+			_ => false,
+		}
+	}
+}
+
+impl Eq for ShaderConstant {}
+
+impl Hash for ShaderConstant {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		std::mem::discriminant(self).hash(state);
+		match self {
+			ShaderConstant::Bool(value) => value.hash(state),
+			ShaderConstant::F32(value) => value.to_bits().hash(state),
+			ShaderConstant::I32(value) => value.hash(state),
+			ShaderConstant::U32(value) => value.hash(state),
+		}
+	}
+}
+
+// Everything that goes into compiling a shader, so each combination can be parsed and given a pipeline of its own.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub(crate) struct ShaderVariant {
+	pub shader: String,
+	pub shader_defs: Vec<ShaderDefVal>,
+	pub constants: Vec<(String, ShaderConstant)>,
 }
 
 /// A compute action describes the specific action to take during a compute step.
@@ -48,17 +793,52 @@ pub enum ComputeAction {
 		/// The name of the function to run in that shader file.
 		entry_point: String,
 
-		/// The workgroup count in the X dimension.
-		x_workgroup_count: u32,
+		/// Shader defs to compile the shader with, for its `#ifdef`s and `#{NAME}` substitutions. Each distinct set of shader defs gets its own pipeline.
+		shader_defs: Vec<ShaderDefVal>,
+
+		/// Values for pipeline-overridable constants, declared in the shader with `override`, by name. Constants that aren't given here keep the default from the shader. Each distinct set of constants gets its own pipeline. Shaders that declare override constants are parsed directly by naga rather than through Bevy's shader composer, so they can't use imports, including the generated bindings at [BINDINGS_IMPORT_PATH](crate::BINDINGS_IMPORT_PATH). Use shader defs instead for shaders that import their bindings.
+		constants: Vec<(String, ShaderConstant)>,
+
+		/// Bytes to set as push constants before the shader is dispatched, which the shader can read from a `var<push_constant>`. These are a cheaper way to give a shader a few small parameters, like a pass index, than a uniform buffer. If this isn't empty, the pipeline declares a push constant range covering all of it, starting at offset 0. The length must be a multiple of 4, and no more than the device's `max_push_constant_size`. This needs the `PUSH_CONSTANTS` feature, which Bevy enables by default on devices that support it. It isn't available on WebGPU.
+		push_constants: Vec<u8>,
 
-		/// The workgroup count in the Y dimension.
-		y_workgroup_count: u32,
+		/// Which of the [ShaderBufferSet](crate::ShaderBufferSet)'s bind groups this shader uses. The group at each position in the list is bound to that `@group` index in the shader, so `Some(vec![2, 0])` binds the set's group 2 as `@group(0)` and group 0 as `@group(1)`. The pipeline is built with only these groups, so the shader only has to declare the bindings it actually uses. If this isn't provided, every group is bound at its own index, which means the shader has to be compatible with all of them.
+		bind_groups: Option<Vec<u32>>,
 
-		/// The workgroup count in the Z dimension.
-		z_workgroup_count: u32,
+		/// How many workgroups to dispatch. See [DispatchSize] for the options.
+		dispatch: DispatchSize,
 	},
 
-	/// This action copies the contents of a buffer back to the CPU. When this runs, it will throw a [CopyBufferEvent](crate::CopyBufferEvent), which contains the data. This is fairly slow, and actually takes two iterations to run, because the data must first be copied into an intermediate buffer before being copied to the CPU. It's highly recommended that if this is on a compute task that runs for many iterations, it's run with a max frequency. But keep in mind that because it takes two iterations to run, the frequency with which you will recieve data will be half the specified frequency.
+	/// This action runs a specific shader, like [RunShader](ComputeAction::RunShader), except the workgroup counts are read from a GPU buffer when it's dispatched. This lets shaders decide how much work the next shader has to do, without the counts ever having to come back to the CPU.
+	///
+	/// The workgroup counts are three consecutive `u32`s, in the X, Y and Z dimensions. The easiest way to make a buffer for them is with [add_indirect_args](crate::ShaderBufferSet::add_indirect_args). They're copied out of the buffer right before the shader is dispatched, so it's fine for shaders, even this one, to write to that buffer.
+	RunShaderIndirect {
+		/// The Bevy asset path to the shader file to run.
+		shader: String,
+
+		/// The name of the function to run in that shader file.
+		entry_point: String,
+
+		/// Shader defs to compile the shader with. See [RunShader](ComputeAction::RunShader::shader_defs).
+		shader_defs: Vec<ShaderDefVal>,
+
+		/// Values for pipeline-overridable constants, by name. See [RunShader](ComputeAction::RunShader::constants).
+		constants: Vec<(String, ShaderConstant)>,
+
+		/// Bytes to set as push constants before the shader is dispatched. See [RunShader](ComputeAction::RunShader::push_constants).
+		push_constants: Vec<u8>,
+
+		/// Which of the [ShaderBufferSet](crate::ShaderBufferSet)'s bind groups this shader uses. See [RunShader](ComputeAction::RunShader::bind_groups).
+		bind_groups: Option<Vec<u32>>,
+
+		/// The buffer holding the workgroup counts. It must have been created with [BufferUsages::INDIRECT](bevy::render::render_resource::BufferUsages::INDIRECT) and [BufferUsages::COPY_SRC](bevy::render::render_resource::BufferUsages::COPY_SRC), which [add_indirect_args](crate::ShaderBufferSet::add_indirect_args) does for you. If this is a double buffer, the counts are read from the front buffer.
+		args: ShaderBufferHandle,
+
+		/// The offset in bytes into the buffer where the workgroup counts start. It must be a multiple of 4.
+		offset: u64,
+	},
+
+	/// This action copies the contents of a buffer back to the CPU. When this runs, it will throw a [CopyBufferEvent](crate::CopyBufferEvent), which contains the data. The data is copied into a staging buffer and read once the GPU is done with it, without ever waiting on it, so the event arrives a frame or two after the step runs. This is fairly slow, so it's highly recommended that if this is on a compute task that runs for many iterations, it's run with a [schedule](ComputeSchedule) that skips most of them. It only runs once per frame, on the first iteration it's scheduled for.
 	CopyBuffer {
 		/// The buffer to copy out of.
 		src: ShaderBufferHandle,
@@ -69,4 +849,145 @@ pub enum ComputeAction {
 		/// The double buffer to swap.
 		buffer: ShaderBufferHandle,
 	},
+
+	/// This action sets part or all of a buffer to zero. It's recorded in order with the other steps, so it's the way to reset an accumulator or an atomic counter between dispatches, without a shader that does nothing but clear it. The buffer must be a storage or uniform buffer created with [BufferUsages::COPY_DST](bevy::render::render_resource::BufferUsages::COPY_DST).
+	ClearBuffer {
+		/// The buffer to clear.
+		buffer: ShaderBufferHandle,
+
+		/// The range of bytes to clear. Both ends must be multiples of 4. If this isn't provided, the whole buffer is cleared.
+		range: Option<Range<u64>>,
+
+		/// Which buffer to clear, if this is a double buffer. Any other buffer only has a front buffer, so this has to be [Front](BufferSide::Front) for them.
+		side: BufferSide,
+	},
+
+	/// This action fills a buffer with a repeating 4-byte value, like [ClearBuffer](ComputeAction::ClearBuffer) but with something other than zero. To fill it with a float, pass its bits from [f32::to_bits]. The buffer must be a storage or uniform buffer created with [BufferUsages::COPY_DST](bevy::render::render_resource::BufferUsages::COPY_DST), and its size must be a multiple of 4. The value is copied in from a small buffer holding it repeated, which is made when the task starts. Buffers bigger than 64 KiB are filled a 64 KiB chunk at a time.
+	FillBuffer {
+		/// The buffer to fill.
+		buffer: ShaderBufferHandle,
+
+		/// The value to repeat across the buffer, written little-endian.
+		value: u32,
+
+		/// Which buffer to fill, if this is a double buffer. Any other buffer only has a front buffer, so this has to be [Front](BufferSide::Front) for them.
+		side: BufferSide,
+	},
+}
+
+/// Which of a double buffer's two buffers an action works on. Since [SwapBuffers](ComputeAction::SwapBuffers) changes which is which, this is whichever buffer is the front or back one when the step runs.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug, Deserialize)]
+pub enum BufferSide {
+	/// The front buffer, that shaders read from.
+	#[default]
+	Front,
+
+	/// The back buffer, that shaders write to.
+	Back,
+}
+
+impl BufferSide {
+	// The other side, for a double buffer that's been swapped since the frame started.
+	pub(crate) fn other(self) -> Self {
+		match self {
+			BufferSide::Front => BufferSide::Back,
+			BufferSide::Back => BufferSide::Front,
+		}
+	}
+}
+
+impl ComputeAction {
+	// The shader this action runs, along with everything it's compiled with, if it runs one.
+	pub(crate) fn shader_variant(&self) -> Option<ShaderVariant> {
+		match self {
+			ComputeAction::RunShader { shader, shader_defs, constants, .. }
+			| ComputeAction::RunShaderIndirect { shader, shader_defs, constants, .. } => {
+				Some(ShaderVariant { shader: shader.clone(), shader_defs: shader_defs.clone(), constants: constants.clone() })
+			}
+			_ => None,
+		}
+	}
+
+	// The shader and entry point this action runs, if it runs one, for error reports.
+	pub(crate) fn shader_and_entry_point(&self) -> (Option<String>, Option<String>) {
+		match self {
+			ComputeAction::RunShader { shader, entry_point, .. }
+			| ComputeAction::RunShaderIndirect { shader, entry_point, .. } => (Some(shader.clone()), Some(entry_point.clone())),
+			_ => (None, None),
+		}
+	}
+
+	// The bind groups this action's shader uses, if it only uses some of them.
+	pub(crate) fn bind_groups(&self) -> Option<&[u32]> {
+		match self {
+			ComputeAction::RunShader { bind_groups, .. } | ComputeAction::RunShaderIndirect { bind_groups, .. } => {
+				bind_groups.as_deref()
+			}
+			_ => None,
+		}
+	}
+
+	// The push constants this action sets, if it runs a shader.
+	pub(crate) fn push_constants(&self) -> &[u8] {
+		match self {
+			ComputeAction::RunShader { push_constants, .. } | ComputeAction::RunShaderIndirect { push_constants, .. } => {
+				push_constants
+			}
+			_ => &[],
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::test_fixtures::{task, A};
+
+	fn flatten(elements: &[ComputeSequenceElement]) -> (Vec<String>, Vec<(usize, usize, String)>) {
+		let mut tasks = Vec::new();
+		let mut loops = Vec::new();
+		flatten_elements(elements, &mut tasks, &mut loops);
+		let loops = loops
+			.into_iter()
+			.map(|compute_loop| {
+				let kind = match compute_loop.kind {
+					ComputeLoopKind::Repeat(count) => format!("Repeat({})", count),
+					ComputeLoopKind::Forever => "Forever".to_owned(),
+					ComputeLoopKind::Until(condition) => format!("Until({})", condition.buffer),
+				};
+				(compute_loop.start, compute_loop.end, kind)
+			})
+			.collect();
+		(tasks.into_iter().filter_map(|task| task.label).collect(), loops)
+	}
+
+	#[test]
+	fn tasks_are_flattened_in_order() {
+		let (tasks, loops) = flatten(&[
+			task("a"),
+			ComputeSequenceElement::repeat(2, vec![task("b"), task("c")]),
+			task("d"),
+			ComputeSequenceElement::until(ComputeCondition::new(A, |done: u32| done != 0), vec![task("e")]),
+		]);
+		assert_eq!(tasks, ["a", "b", "c", "d", "e"]);
+		assert_eq!(loops, [(1, 3, "Repeat(2)".to_owned()), (4, 5, "Until({ id(1) })".to_owned())]);
+	}
+
+	#[test]
+	fn inner_loops_come_first() {
+		let (tasks, loops) = flatten(&[ComputeSequenceElement::forever(vec![
+			task("a"),
+			ComputeSequenceElement::repeat(3, vec![task("b"), ComputeSequenceElement::repeat(2, vec![task("c")])]),
+		])]);
+		assert_eq!(tasks, ["a", "b", "c"]);
+		assert_eq!(loops, [(2, 3, "Repeat(2)".to_owned()), (1, 3, "Repeat(3)".to_owned()), (0, 3, "Forever".to_owned())]);
+	}
+
+	#[test]
+	fn empty_loops_are_left_out() {
+		let (tasks, loops) =
+			flatten(&[task("a"), ComputeSequenceElement::Loop(vec![ComputeSequenceElement::Loop(Vec::new())]), task("b")]);
+		assert_eq!(tasks, ["a", "b"]);
+		assert_eq!(loops, []);
+	}
 }
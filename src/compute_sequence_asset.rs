@@ -0,0 +1,724 @@
+use std::{
+	error::Error,
+	fmt::{Display, Formatter},
+	num::NonZeroU32,
+	ops::Range,
+	time::Duration,
+};
+
+use bevy::{
+	asset::{io::Reader, AssetLoader, LoadContext, LoadState},
+	prelude::*,
+	render::render_resource::ShaderDefVal,
+};
+use bevy_platform::collections::hash_map::HashMap;
+use serde::Deserialize;
+
+use super::{
+	compute_sequence::{
+		flatten_elements, BufferSide, ComputeAction, ComputeSchedule, ComputeSequenceElement, ComputeSequenceId,
+		ComputeSequences, ComputeStep, ComputeStepOrder, ComputeTask, DispatchSize, ShaderConstant,
+	},
+	ComputeErrorEvent, ComputeSequenceDoneEvent, ComputeSequenceOutcome, StartComputeEvent, DEFAULT_PIPELINE_TIMEOUT,
+};
+use crate::shader_buffer_set::{ShaderBufferHandle, ShaderBufferSet};
+
+/// A compute sequence loaded from a `.compute.ron` file, so it can be changed without recompiling. It describes the same things as a [StartComputeEvent], except that buffers are referred to by the names given to them with [name_buffer](ShaderBufferSet::name_buffer), and durations are given in seconds, which have to be more than zero. Task conditions can't be written down in a file, since they're Rust closures.
+///
+/// Start one with a [StartComputeAssetEvent]. While the sequence is running, saving changes to the file restarts it with the new version, as long as Bevy's asset file watching is turned on.
+#[derive(Asset, TypePath, Deserialize, Clone)]
+pub struct ComputeSequenceAsset {
+	/// The elements of the sequence. See [ComputeSequenceElement].
+	pub tasks: Vec<ComputeElementDescription>,
+
+	/// The name of the iteration buffer, if there is one. See [StartComputeEvent::iteration_buffer].
+	#[serde(default)]
+	pub iteration_buffer: Option<String>,
+
+	/// See [StartComputeEvent::wait_for_pipelines].
+	#[serde(default)]
+	pub wait_for_pipelines: bool,
+
+	/// How often to throw a [ComputeProgressEvent](crate::ComputeProgressEvent), in seconds. See [StartComputeEvent::progress_interval].
+	#[serde(default)]
+	pub progress_interval: Option<f32>,
+
+	/// How long pipelines can take to be ready before the sequence fails, in seconds. If this isn't provided, it's [DEFAULT_PIPELINE_TIMEOUT]. See [StartComputeEvent::pipeline_timeout].
+	#[serde(default)]
+	pub pipeline_timeout: Option<f32>,
+}
+
+/// An element of a [ComputeSequenceAsset]. See [ComputeSequenceElement].
+#[derive(Deserialize, Clone)]
+pub enum ComputeElementDescription {
+	/// A single task.
+	Task(ComputeTaskDescription),
+
+	/// Runs the elements this many times over before moving on.
+	Repeat {
+		/// The number of times to run the elements.
+		count: NonZeroU32,
+
+		/// The elements to run.
+		elements: Vec<ComputeElementDescription>,
+	},
+
+	/// Runs the elements over and over again, forever.
+	Loop(Vec<ComputeElementDescription>),
+}
+
+/// A task in a [ComputeSequenceAsset]. See [ComputeTask].
+#[derive(Deserialize, Clone)]
+pub struct ComputeTaskDescription {
+	/// See [ComputeTask::label].
+	#[serde(default)]
+	pub label: Option<String>,
+
+	/// See [ComputeTask::iterations].
+	#[serde(default)]
+	pub iterations: Option<NonZeroU32>,
+
+	/// How long to run the task for, in seconds. See [ComputeTask::duration].
+	#[serde(default)]
+	pub duration: Option<f32>,
+
+	/// See [ComputeTask::iterations_per_frame].
+	#[serde(default)]
+	pub iterations_per_frame: Option<NonZeroU32>,
+
+	/// See [ComputeTask::steps].
+	pub steps: Vec<ComputeStepDescription>,
+
+	/// See [ComputeTask::step_order].
+	#[serde(default)]
+	pub step_order: ComputeStepOrder,
+}
+
+/// A step in a [ComputeSequenceAsset]. See [ComputeStep].
+#[derive(Deserialize, Clone)]
+pub struct ComputeStepDescription {
+	/// See [ComputeStep::schedule].
+	#[serde(default)]
+	pub schedule: ComputeSchedule,
+
+	/// See [ComputeStep::action].
+	pub action: ComputeActionDescription,
+
+	/// The names of the buffers the step reads. See [ComputeStep::reads].
+	#[serde(default)]
+	pub reads: Vec<String>,
+
+	/// The names of the buffers the step writes. See [ComputeStep::writes].
+	#[serde(default)]
+	pub writes: Vec<String>,
+}
+
+/// An action in a [ComputeSequenceAsset]. See [ComputeAction].
+#[derive(Deserialize, Clone)]
+pub enum ComputeActionDescription {
+	/// See [ComputeAction::RunShader].
+	RunShader {
+		/// The Bevy asset path to the shader file to run.
+		shader: String,
+
+		/// The name of the function to run in that shader file.
+		entry_point: String,
+
+		/// Shader defs to compile the shader with.
+		#[serde(default)]
+		shader_defs: Vec<ShaderDefDescription>,
+
+		/// Values for the shader's override constants, by name.
+		#[serde(default)]
+		constants: Vec<(String, ShaderConstant)>,
+
+		/// Bytes to set as push constants.
+		#[serde(default)]
+		push_constants: Vec<u8>,
+
+		/// Which of the [ShaderBufferSet]'s bind groups the shader uses.
+		#[serde(default)]
+		bind_groups: Option<Vec<u32>>,
+
+		/// How many workgroups to dispatch.
+		dispatch: DispatchSizeDescription,
+	},
+
+	/// See [ComputeAction::RunShaderIndirect].
+	RunShaderIndirect {
+		/// The Bevy asset path to the shader file to run.
+		shader: String,
+
+		/// The name of the function to run in that shader file.
+		entry_point: String,
+
+		/// Shader defs to compile the shader with.
+		#[serde(default)]
+		shader_defs: Vec<ShaderDefDescription>,
+
+		/// Values for the shader's override constants, by name.
+		#[serde(default)]
+		constants: Vec<(String, ShaderConstant)>,
+
+		/// Bytes to set as push constants.
+		#[serde(default)]
+		push_constants: Vec<u8>,
+
+		/// Which of the [ShaderBufferSet]'s bind groups the shader uses.
+		#[serde(default)]
+		bind_groups: Option<Vec<u32>>,
+
+		/// The name of the buffer holding the workgroup counts.
+		args: String,
+
+		/// The offset of the workgroup counts in that buffer, in bytes.
+		#[serde(default)]
+		offset: u64,
+	},
+
+	/// See [ComputeAction::CopyBuffer].
+	CopyBuffer {
+		/// The name of the buffer to copy.
+		src: String,
+	},
+
+	/// See [ComputeAction::SwapBuffers].
+	SwapBuffers {
+		/// The name of the double buffer to swap.
+		buffer: String,
+	},
+
+	/// See [ComputeAction::ClearBuffer].
+	ClearBuffer {
+		/// The name of the buffer to clear.
+		buffer: String,
+
+		/// The range of bytes to clear, like `Some((start: 0, end: 16))`. The whole buffer is cleared if this is left out.
+		#[serde(default)]
+		range: Option<Range<u64>>,
+
+		/// Which buffer to clear, if it's a double buffer.
+		#[serde(default)]
+		side: BufferSide,
+	},
+
+	/// See [ComputeAction::FillBuffer].
+	FillBuffer {
+		/// The name of the buffer to fill.
+		buffer: String,
+
+		/// The value to repeat across the buffer.
+		value: u32,
+
+		/// Which buffer to fill, if it's a double buffer.
+		#[serde(default)]
+		side: BufferSide,
+	},
+}
+
+/// A dispatch size in a [ComputeSequenceAsset]. See [DispatchSize].
+#[derive(Deserialize, Clone)]
+pub enum DispatchSizeDescription {
+	/// See [DispatchSize::Workgroups].
+	Workgroups(u32, u32, u32),
+
+	/// See [DispatchSize::PerTexel]. This holds the name of the texture buffer.
+	PerTexel(String),
+
+	/// See [DispatchSize::PerElement].
+	PerElement {
+		/// The name of the buffer to dispatch over.
+		buffer: String,
+
+		/// The size of each element in bytes.
+		stride: NonZeroU32,
+	},
+}
+
+/// A shader def in a [ComputeSequenceAsset]. See Bevy's [ShaderDefVal].
+#[derive(Deserialize, Clone)]
+pub enum ShaderDefDescription {
+	/// A boolean shader def, with its name and value.
+	Bool(String, bool),
+
+	/// A signed integer shader def, with its name and value.
+	Int(String, i32),
+
+	/// An unsigned integer shader def, with its name and value.
+	UInt(String, u32),
+}
+
+impl From<&ShaderDefDescription> for ShaderDefVal {
+	fn from(def: &ShaderDefDescription) -> Self {
+		match def {
+			ShaderDefDescription::Bool(name, value) => ShaderDefVal::Bool(name.clone(), *value),
+			ShaderDefDescription::Int(name, value) => ShaderDefVal::Int(name.clone(), *value),
+			ShaderDefDescription::UInt(name, value) => ShaderDefVal::UInt(name.clone(), *value),
+		}
+	}
+}
+
+// Something that went wrong building a sequence from an asset, along with the task and step it was in, if any.
+type BuildError = (usize, Option<usize>, String);
+
+// Turns a number of seconds from a file into a duration, which has to be more than zero, just like the durations given
+// to the builders.
+fn seconds(value: f32, name: &str) -> Result<Duration, String> {
+	match Duration::try_from_secs_f32(value) {
+		Ok(duration) if !duration.is_zero() => Ok(duration),
+		_ => Err(format!("The {} has to be a positive number of seconds, but it's {}", name, value)),
+	}
+}
+
+// Turns the descriptions in an asset into a sequence, looking up the buffers by name.
+struct SequenceBuilder<'a> {
+	buffers: &'a ShaderBufferSet,
+	// The index of the next task, counting into loops, for reporting errors.
+	task: usize,
+}
+
+impl SequenceBuilder<'_> {
+	fn buffer(&self, name: &str, step: Option<usize>) -> Result<ShaderBufferHandle, BuildError> {
+		self
+			.buffers
+			.buffer_by_name(name)
+			.ok_or_else(|| (self.task, step, format!("There's no buffer named \"{}\" in the ShaderBufferSet", name)))
+	}
+
+	fn elements(&mut self, elements: &[ComputeElementDescription]) -> Result<Vec<ComputeSequenceElement>, BuildError> {
+		elements
+			.iter()
+			.map(|element| match element {
+				ComputeElementDescription::Task(task) => Ok(ComputeSequenceElement::Task(self.task(task)?)),
+				ComputeElementDescription::Repeat { count, elements } => {
+					Ok(ComputeSequenceElement::Repeat { count: *count, elements: self.elements(elements)? })
+				}
+				ComputeElementDescription::Loop(elements) => Ok(ComputeSequenceElement::Loop(self.elements(elements)?)),
+			})
+			.collect()
+	}
+
+	fn task(&mut self, task: &ComputeTaskDescription) -> Result<ComputeTask, BuildError> {
+		let steps = task.steps.iter().enumerate().map(|(index, step)| self.step(index, step)).collect::<Result<_, _>>()?;
+		let duration = task.duration.map(|duration| seconds(duration, "task's duration")).transpose();
+		let duration = duration.map_err(|error| (self.task, None, error))?;
+		self.task += 1;
+		Ok(ComputeTask {
+			label: task.label.clone(),
+			iterations: task.iterations,
+			duration,
+			iterations_per_frame: task.iterations_per_frame,
+			steps,
+			step_order: task.step_order,
+			condition: None,
+		})
+	}
+
+	fn step(&self, index: usize, step: &ComputeStepDescription) -> Result<ComputeStep, BuildError> {
+		let buffer = |name: &str| self.buffer(name, Some(index));
+		let action = match &step.action {
+			ComputeActionDescription::RunShader {
+				shader,
+				entry_point,
+				shader_defs,
+				constants,
+				push_constants,
+				bind_groups,
+				dispatch,
+			} => ComputeAction::RunShader {
+				shader: shader.clone(),
+				entry_point: entry_point.clone(),
+				shader_defs: shader_defs.iter().map(ShaderDefVal::from).collect(),
+				constants: constants.clone(),
+				push_constants: push_constants.clone(),
+				bind_groups: bind_groups.clone(),
+				dispatch: match dispatch {
+					DispatchSizeDescription::Workgroups(x, y, z) => DispatchSize::Workgroups(*x, *y, *z),
+					DispatchSizeDescription::PerTexel(name) => DispatchSize::PerTexel(buffer(name)?),
+					DispatchSizeDescription::PerElement { buffer: name, stride } => {
+						DispatchSize::PerElement { buffer: buffer(name)?, stride: *stride }
+					}
+				},
+			},
+			ComputeActionDescription::RunShaderIndirect {
+				shader,
+				entry_point,
+				shader_defs,
+				constants,
+				push_constants,
+				bind_groups,
+				args,
+				offset,
+			} => ComputeAction::RunShaderIndirect {
+				shader: shader.clone(),
+				entry_point: entry_point.clone(),
+				shader_defs: shader_defs.iter().map(ShaderDefVal::from).collect(),
+				constants: constants.clone(),
+				push_constants: push_constants.clone(),
+				bind_groups: bind_groups.clone(),
+				args: buffer(args)?,
+				offset: *offset,
+			},
+			ComputeActionDescription::CopyBuffer { src } => ComputeAction::CopyBuffer { src: buffer(src)? },
+			ComputeActionDescription::SwapBuffers { buffer: name } => ComputeAction::SwapBuffers { buffer: buffer(name)? },
+			ComputeActionDescription::ClearBuffer { buffer: name, range, side } => {
+				ComputeAction::ClearBuffer { buffer: buffer(name)?, range: range.clone(), side: *side }
+			}
+			ComputeActionDescription::FillBuffer { buffer: name, value, side } => {
+				ComputeAction::FillBuffer { buffer: buffer(name)?, value: *value, side: *side }
+			}
+		};
+		Ok(ComputeStep {
+			schedule: step.schedule,
+			action,
+			reads: step.reads.iter().map(|name| buffer(name)).collect::<Result<_, _>>()?,
+			writes: step.writes.iter().map(|name| buffer(name)).collect::<Result<_, _>>()?,
+		})
+	}
+}
+
+impl ComputeSequenceAsset {
+	// Builds the event that starts the sequence this asset describes.
+	fn start_event(&self, id: ComputeSequenceId, buffers: &ShaderBufferSet) -> Result<StartComputeEvent, BuildError> {
+		let mut builder = SequenceBuilder { buffers, task: 0 };
+		let iteration_buffer = self.iteration_buffer.as_deref().map(|name| builder.buffer(name, None)).transpose()?;
+		let progress_interval = self.progress_interval.map(|interval| seconds(interval, "progress interval")).transpose();
+		let pipeline_timeout = self.pipeline_timeout.map(|timeout| seconds(timeout, "pipeline timeout")).transpose();
+		let event = StartComputeEvent {
+			id,
+			tasks: builder.elements(&self.tasks)?,
+			iteration_buffer,
+			wait_for_pipelines: self.wait_for_pipelines,
+			progress_interval: progress_interval.map_err(|error| (0, None, error))?,
+			pipeline_timeout: pipeline_timeout.map_err(|error| (0, None, error))?.unwrap_or(DEFAULT_PIPELINE_TIMEOUT),
+		};
+
+		// The sequence is checked here as well as when it starts, so a version that doesn't work doesn't replace one
+		// that's running.
+		let mut tasks = Vec::new();
+		let mut loops = Vec::new();
+		flatten_elements(&event.tasks, &mut tasks, &mut loops);
+		match buffers.sequence_errors(&tasks, &loops).into_iter().next() {
+			Some(error) => Err((error.task, error.step, error.error)),
+			None => Ok(event),
+		}
+	}
+}
+
+/// This event starts a compute sequence from a [ComputeSequenceAsset]. It works just like a [StartComputeEvent], except that if the asset hasn't loaded yet, the sequence starts once it has. If the asset can't be loaded, or it names a buffer that doesn't exist, uses one in a way it can't be used, or gives a duration that isn't more than zero, a [ComputeErrorEvent] is thrown, followed by a [ComputeSequenceDoneEvent] with a [Rejected](ComputeSequenceOutcome::Rejected) outcome.
+#[derive(Event)]
+pub struct StartComputeAssetEvent {
+	/// The id of this sequence. See [StartComputeEvent::id].
+	pub id: ComputeSequenceId,
+
+	/// The handle of the asset to start the sequence from.
+	pub asset: Handle<ComputeSequenceAsset>,
+}
+
+#[derive(Default)]
+pub(crate) struct ComputeSequenceAssetLoader;
+
+/// Something that went wrong loading a [ComputeSequenceAsset].
+#[derive(Debug)]
+pub enum ComputeSequenceAssetError {
+	/// The file couldn't be read.
+	Io(std::io::Error),
+
+	/// The file isn't a valid description of a sequence.
+	Ron(ron::error::SpannedError),
+}
+
+impl Display for ComputeSequenceAssetError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			ComputeSequenceAssetError::Io(e) => write!(f, "Couldn't read the compute sequence: {}", e),
+			ComputeSequenceAssetError::Ron(e) => write!(f, "Couldn't parse the compute sequence: {}", e),
+		}
+	}
+}
+
+impl Error for ComputeSequenceAssetError {}
+
+impl AssetLoader for ComputeSequenceAssetLoader {
+	type Asset = ComputeSequenceAsset;
+	type Settings = ();
+	type Error = ComputeSequenceAssetError;
+
+	async fn load(
+		&self, reader: &mut dyn Reader, _settings: &(), _load_context: &mut LoadContext<'_>,
+	) -> Result<Self::Asset, Self::Error> {
+		let mut bytes = Vec::new();
+		reader.read_to_end(&mut bytes).await.map_err(ComputeSequenceAssetError::Io)?;
+		ron::de::from_bytes(&bytes).map_err(ComputeSequenceAssetError::Ron)
+	}
+
+	fn extensions(&self) -> &[&str] { &["compute.ron"] }
+}
+
+// The sequences that were started from assets.
+#[derive(Resource, Default)]
+pub(crate) struct ComputeSequenceAssets(HashMap<ComputeSequenceId, TrackedAsset>);
+
+pub(crate) struct TrackedAsset {
+	handle: Handle<ComputeSequenceAsset>,
+	// Whether the sequence is waiting to be started, or restarted, from the latest version of the asset.
+	pending: bool,
+	started: bool,
+	// The generation of the sequence that was last started from the asset, once it's been set up.
+	generation: Option<u64>,
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn start_compute_assets(
+	mut tracked: ResMut<ComputeSequenceAssets>, mut start_asset_events: EventReader<StartComputeAssetEvent>,
+	mut asset_events: EventReader<AssetEvent<ComputeSequenceAsset>>, assets: Res<Assets<ComputeSequenceAsset>>,
+	asset_server: Res<AssetServer>, buffers: Res<ShaderBufferSet>, sequences: Res<ComputeSequences>,
+	mut start_events: EventWriter<StartComputeEvent>, mut error_events: EventWriter<ComputeErrorEvent>,
+	mut done_events: EventWriter<ComputeSequenceDoneEvent>,
+) {
+	// Sequences that have ended don't need to be restarted when their asset changes, and neither do ones that have
+	// been replaced by a sequence that wasn't started from the asset.
+	tracked.0.retain(|id, asset| match (sequences.0.get(id), asset.generation) {
+		(Some(sequence), Some(generation)) => sequence.generation == generation,
+		(Some(sequence), None) => {
+			// The start event is set up the same frame it's sent, so this is the sequence the asset started.
+			if asset.started {
+				asset.generation = Some(sequence.generation);
+			}
+			true
+		}
+		(None, _) => asset.pending,
+	});
+
+	for event in start_asset_events.read() {
+		tracked
+			.0
+			.insert(event.id, TrackedAsset { handle: event.asset.clone(), pending: true, started: false, generation: None });
+	}
+	for event in asset_events.read() {
+		if let AssetEvent::Modified { id } = event {
+			for asset in tracked.0.values_mut().filter(|asset| asset.handle.id() == *id) {
+				asset.pending = true;
+			}
+		}
+	}
+
+	let mut failed = Vec::new();
+	for (id, tracked_asset) in tracked.0.iter_mut().filter(|(_, asset)| asset.pending) {
+		let result = match assets.get(&tracked_asset.handle) {
+			Some(asset) => asset.start_event(*id, &buffers),
+			None => match asset_server.get_load_state(tracked_asset.handle.id()) {
+				Some(LoadState::Failed(e)) => Err((0, None, e.to_string())),
+				_ => continue,
+			},
+		};
+		tracked_asset.pending = false;
+		match result {
+			Ok(event) => {
+				start_events.write(event);
+				tracked_asset.started = true;
+				tracked_asset.generation = None;
+			}
+			// If a new version of a running sequence doesn't work, the old one is left running.
+			Err((_, _, error)) if tracked_asset.started => {
+				warn!("Couldn't restart compute sequence {} with the changes to its asset: {}", id, error);
+			}
+			Err((task, step, error)) => {
+				error!("Couldn't start compute sequence {} from its asset: {}", id, error);
+				error_events.write(ComputeErrorEvent { sequence: *id, task, step, shader: None, entry_point: None, error });
+				done_events.write(ComputeSequenceDoneEvent { sequence: *id, outcome: ComputeSequenceOutcome::Rejected });
+				failed.push(*id);
+			}
+		}
+	}
+	for id in failed {
+		tracked.0.remove(&id);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn parse(source: &str) -> Result<ComputeSequenceAsset, ron::error::SpannedError> { ron::de::from_str(source) }
+
+	fn task(element: &ComputeElementDescription) -> &ComputeTaskDescription {
+		match element {
+			ComputeElementDescription::Task(task) => task,
+			_ => panic!("Expected a task"),
+		}
+	}
+
+	#[test]
+	fn readme_example_parses() {
+		let asset = parse(
+			r#"#![enable(implicit_some)]
+(
+    tasks: [
+        Task((
+            label: "Init",
+            iterations: 1,
+            steps: [
+                (action: RunShader(shader: "shaders/game_of_life.wgsl", entry_point: "init", dispatch: PerTexel("cells"))),
+                (action: SwapBuffers(buffer: "cells")),
+            ],
+        )),
+        Task((
+            label: "Update",
+            steps: [
+                (schedule: MaxFrequency(10), action: RunShader(shader: "shaders/game_of_life.wgsl", entry_point: "update", dispatch: PerTexel("cells"))),
+                (schedule: MaxFrequency(10), action: SwapBuffers(buffer: "cells")),
+            ],
+        )),
+    ],
+)"#,
+		)
+		.unwrap();
+		assert_eq!(asset.tasks.len(), 2);
+		assert_eq!(asset.iteration_buffer, None);
+		assert!(!asset.wait_for_pipelines);
+		assert_eq!(asset.pipeline_timeout, None);
+
+		let init = task(&asset.tasks[0]);
+		assert_eq!(init.label.as_deref(), Some("Init"));
+		assert_eq!(init.iterations, NonZeroU32::new(1));
+		assert_eq!(init.step_order, ComputeStepOrder::InOrder);
+		assert_eq!(init.steps[0].schedule, ComputeSchedule::Always);
+		assert!(matches!(
+			&init.steps[0].action,
+			ComputeActionDescription::RunShader { entry_point, dispatch: DispatchSizeDescription::PerTexel(texture), shader_defs, .. }
+				if entry_point == "init" && texture == "cells" && shader_defs.is_empty()
+		));
+		assert!(matches!(&init.steps[1].action, ComputeActionDescription::SwapBuffers { buffer } if buffer == "cells"));
+
+		let update = task(&asset.tasks[1]);
+		assert_eq!(update.iterations, None);
+		assert_eq!(update.steps[1].schedule, ComputeSchedule::MaxFrequency(NonZeroU32::new(10).unwrap()));
+	}
+
+	#[test]
+	fn loops_and_buffer_actions_parse() {
+		let asset = parse(
+			r#"(
+    tasks: [
+        Repeat(count: 3, elements: [
+            Loop([
+                Task((
+                    duration: Some(2.5),
+                    step_order: ByBufferAccess,
+                    steps: [
+                        (action: ClearBuffer(buffer: "counts", range: Some((start: 0, end: 16)))),
+                        (action: FillBuffer(buffer: "cells", value: 7, side: Back)),
+                        (action: CopyBuffer(src: "counts"), reads: ["cells"]),
+                    ],
+                )),
+            ]),
+        ]),
+    ],
+    pipeline_timeout: Some(5.0),
+)"#,
+		)
+		.unwrap();
+		assert_eq!(asset.pipeline_timeout, Some(5.0));
+		let ComputeElementDescription::Repeat { count, elements } = &asset.tasks[0] else {
+			panic!("Expected a repeat");
+		};
+		assert_eq!(count.get(), 3);
+		let ComputeElementDescription::Loop(elements) = &elements[0] else {
+			panic!("Expected a loop");
+		};
+		let task = task(&elements[0]);
+		assert_eq!(task.duration, Some(2.5));
+		assert_eq!(task.step_order, ComputeStepOrder::ByBufferAccess);
+		assert!(matches!(
+			&task.steps[0].action,
+			ComputeActionDescription::ClearBuffer { buffer, range: Some(range), side: BufferSide::Front }
+				if buffer == "counts" && *range == (0..16)
+		));
+		assert!(matches!(
+			&task.steps[1].action,
+			ComputeActionDescription::FillBuffer { value: 7, side: BufferSide::Back, .. }
+		));
+		assert_eq!(task.steps[2].reads, ["cells"]);
+		assert!(task.steps[2].writes.is_empty());
+	}
+
+	#[test]
+	fn durations_have_to_be_positive() {
+		let asset = |task_duration: &str, progress_interval: &str, pipeline_timeout: &str| {
+			parse(&format!(
+				r#"(
+    tasks: [
+        Task((steps: [(action: RunShader(shader: "a.wgsl", entry_point: "main", dispatch: Workgroups(1, 1, 1)))])),
+        Task((
+            duration: {},
+            steps: [(action: RunShader(shader: "a.wgsl", entry_point: "main", dispatch: Workgroups(1, 1, 1)))],
+        )),
+    ],
+    progress_interval: {},
+    pipeline_timeout: {},
+)"#,
+				task_duration, progress_interval, pipeline_timeout
+			))
+			.unwrap()
+			.start_event(ComputeSequenceId(0), &ShaderBufferSet::new())
+			.map(|_| ())
+		};
+		assert!(asset("Some(0.5)", "Some(0.25)", "Some(10.0)").is_ok());
+		assert!(asset("None", "None", "None").is_ok());
+		for (duration, error) in [("-1.0", "-1"), ("0.0", "0"), ("inf", "inf"), ("NaN", "NaN")] {
+			let duration = format!("Some({})", duration);
+			assert_eq!(
+				asset(&duration, "None", "None").unwrap_err(),
+				(1, None, format!("The task's duration has to be a positive number of seconds, but it's {}", error))
+			);
+			assert_eq!(
+				asset("None", &duration, "None").unwrap_err(),
+				(0, None, format!("The progress interval has to be a positive number of seconds, but it's {}", error))
+			);
+			assert_eq!(
+				asset("None", "None", &duration).unwrap_err(),
+				(0, None, format!("The pipeline timeout has to be a positive number of seconds, but it's {}", error))
+			);
+		}
+	}
+
+	#[test]
+	fn invalid_files_are_rejected() {
+		// A repeat has to run at least once.
+		assert!(parse("(tasks: [Repeat(count: 0, elements: [])])").is_err());
+		// Every task needs its steps.
+		assert!(parse("(tasks: [Task((label: Some(\"a\")))])").is_err());
+		assert!(parse("(tasks: [Task((steps: [(action: Explode)]))])").is_err());
+	}
+
+	#[test]
+	fn buffers_are_looked_up_by_name() {
+		let asset = parse(
+			r#"(
+    tasks: [
+        Task((steps: [(action: RunShader(shader: "a.wgsl", entry_point: "main", dispatch: Workgroups(1, 1, 1)))])),
+        Repeat(count: 2, elements: [
+            Task((steps: [
+                (action: RunShader(shader: "a.wgsl", entry_point: "main", dispatch: Workgroups(1, 1, 1))),
+                (action: SwapBuffers(buffer: "cells")),
+            ])),
+        ]),
+    ],
+)"#,
+		)
+		.unwrap();
+		let Err(error) = asset.start_event(ComputeSequenceId(0), &ShaderBufferSet::new()) else {
+			panic!("A sequence naming a buffer that doesn't exist was started");
+		};
+		assert_eq!(error, (1, Some(1), "There's no buffer named \"cells\" in the ShaderBufferSet".to_owned()));
+
+		let mut asset = asset;
+		asset.tasks.truncate(1);
+		asset.progress_interval = Some(0.5);
+		let event = asset.start_event(ComputeSequenceId(3), &ShaderBufferSet::new()).unwrap();
+		assert_eq!(event.id, ComputeSequenceId(3));
+		assert_eq!(event.progress_interval, Some(Duration::from_millis(500)));
+		assert_eq!(event.pipeline_timeout, DEFAULT_PIPELINE_TIMEOUT);
+	}
+}
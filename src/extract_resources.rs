@@ -3,19 +3,16 @@ use bevy::{
 	render::{extract_resource::ExtractResource, Extract},
 };
 
-use super::compute_sequence::ComputeSequence;
-
-pub fn extract_resources(
-	mut commands: Commands, main_data: Extract<Option<Res<ComputeSequence>>>,
-	target_data: Option<ResMut<ComputeSequence>>,
+pub fn extract_resources<R: Resource + ExtractResource<Source = R>>(
+	mut commands: Commands, main_data: Extract<Option<Res<R>>>, target_data: Option<ResMut<R>>,
 ) {
 	if let Some(main_data) = &*main_data {
 		if let Some(mut target_data) = target_data {
 			if main_data.is_changed() {
-				*target_data = ComputeSequence::extract_resource(&main_data);
+				*target_data = R::extract_resource(main_data);
 			}
 		} else {
-			commands.insert_resource(ComputeSequence::extract_resource(&main_data));
+			commands.insert_resource(R::extract_resource(main_data));
 		}
 	}
 }
@@ -23,6 +23,7 @@
 //! - [add_storage_uninit](ShaderBufferSet::add_storage_uninit) - Add an uninitialized storage buffer.
 //! - [add_storage_zeroed](ShaderBufferSet::add_storage_zeroed) - Add a storage buffer filled with 0 bytes.
 //! - [add_storage_init](ShaderBufferSet::add_storage_init) - Add a storage buffer with initial data provided.
+//! - [add_indirect_args](ShaderBufferSet::add_indirect_args) - Add a storage buffer holding workgroup counts for indirect dispatches.
 //! - [add_uniform_init](ShaderBufferSet::add_uniform_init) - Add a uniform buffer with initial data provided.
 //! - [add_texture_fill](ShaderBufferSet::add_texture_fill) - Add a texture buffer filled with a solid color.
 //!
@@ -42,25 +43,159 @@
 //!
 //! ## Setting Buffer Contents
 //!
-//! Buffer contents are internally just arrays of bytes, but they can be converted from more complicated data structures. This API uses the [ShaderType](bevy::render::render_resource::ShaderType) trait to do that, which comes from the Encase crate that is included with Bevy. You can put `#[derive(ShaderType)]` in front of any data type, as long as all fields in that data type also implement [ShaderType](bevy::render::render_resource::ShaderType). All basic numeric types already do, along with any array, tuple or [Vec] of types that implement [ShaderType](bevy::render::render_resource::ShaderType). Which makes it very easy to pass whatever structured data you want into your shaders. Just be careful, because the shader has to specify the structure of the data independently, and if there's a mismatch it will only throw an error if they're a different size.
+//! Buffer contents are internally just arrays of bytes, but they can be converted from more complicated data structures. This API uses the [ShaderType](bevy::render::render_resource::ShaderType) trait to do that, which comes from the Encase crate that is included with Bevy. You can put `#[derive(ShaderType)]` in front of any data type, as long as all fields in that data type also implement [ShaderType](bevy::render::render_resource::ShaderType). All basic numeric types already do, along with any array, tuple or [Vec] of types that implement [ShaderType](bevy::render::render_resource::ShaderType). Which makes it very easy to pass whatever structured data you want into your shaders. Just be careful, because the shader has to specify the structure of the data independently. Before a shader runs, the resources it uses are checked against the buffers bound in the same places: the kind of resource, whether it's read-only, the texture format, and that the buffer is at least as big as the type the shader declares. Any mismatches fail the sequence with a [ComputeErrorEvent] naming both the buffer and the WGSL variable. But the fields inside a struct aren't checked, so if they're in a different order, or are different types of the same size, nothing will notice.
+//!
+//! ## Generated Bindings
+//!
+//! Rather than writing out the `@group(0) @binding(0) var ...` line for every buffer in every shader, and keeping them all in step with the [ShaderBufferSet] by hand, shaders can import them. Give a buffer a name with [name_buffer](ShaderBufferSet::name_buffer), and for storage and uniform buffers, a WGSL type with [set_buffer_type](ShaderBufferSet::set_buffer_type), and it's declared in a module the plugin generates from the [ShaderBufferSet] and registers as a shader, with its group and binding numbers, access mode and texture format filled in. Double buffers are declared as two variables, with `_front` and `_back` added to the name. Shaders that declare override constants can't import it, as explained in the "Shader Defs and Override Constants" section below. The module is updated whenever the [ShaderBufferSet] changes, and any shaders that import it are recompiled. The generated source can be looked at with [wgsl_bindings](ShaderBufferSet::wgsl_bindings). For example, if the Game of Life example named its texture `cells`, its shader could start with:
+//!
+//! ```wgsl
+//! #import bevy_compute::bindings::{cells_front, cells_back}
+//! ```
+//!
+//! The group numbers are the [ShaderBufferSet]'s own, so they only line up for shader steps that don't pick their own bind groups.
+//!
+//! The WGSL type of a buffer can also come from the Rust type it holds, with [set_buffer_type_of](ShaderBufferSet::set_buffer_type_of). The scalar, vector and matrix types WGSL shares with Rust all work, as do arrays and `Vec`s of them, and structs declared with the [wgsl_struct] macro. That's written around an ordinary struct definition that derives [ShaderType](bevy::render::render_resource::ShaderType), and generates a WGSL struct with the same fields in the same order, which is included in the generated module along with the declaration of any buffer that uses it. Since [ShaderType](bevy::render::render_resource::ShaderType) lays out the Rust struct by the same rules WGSL uses, the padding and alignment come out the same on both sides without having to be written by hand, and [set_buffer_type_of](ShaderBufferSet::set_buffer_type_of) checks the field offsets and sizes match, so anything that changes the layout on one side only is caught straight away. It also checks that two different structs don't end up with the same name, like Rust structs of the same name from different modules, since the generated module can only declare one of them.
+//!
+//! ```Rust
+//! wgsl_struct! {
+//!     #[derive(ShaderType, Clone, Default)]
+//!     struct Particle {
+//!         position: Vec3,
+//!         age: f32,
+//!         velocity: Vec3,
+//!     }
+//! }
+//!
+//! buffer_set.name_buffer(particles, "particles");
+//! buffer_set.set_buffer_type_of::<Vec<Particle>>(particles);
+//! ```
 //!
 //! # Starting the Compute Shader
 //!
-//! To start running the compute shaders, you need to throw a [StartComputeEvent]. This contains a [Vec] of [ComputeTask]s, which details all the compute tasks to complete, and a optional [ShaderBufferHandle], for the optional iteration buffer.
+//! To start running the compute shaders, you need to throw a [StartComputeEvent]. This contains a [ComputeSequenceId] that you pick to identify the sequence, a [Vec] of [ComputeSequenceElement]s, which details all the compute tasks to complete, and a optional [ShaderBufferHandle], for the optional iteration buffer.
 //!
 //! ## ComputeTask
 //!
-//! A compute task represents one stage of your compute shader program. The compute task is optionally provided a number of iterations, and it will run for that many ticks before moving on to the next task. If that's not provided, it'll run forever. A compute task is also given a list of [ComputeStep]s, each of which is a specific shader to run, or other compute-related action to take, in order, each iteration. It can also be given an optional label, which is used to identify the task in the [ComputeTaskDoneEvent] that's thrown when the task completes.
+//! A compute task represents one stage of your compute shader program. The compute task is optionally provided a number of iterations, and it will run for that many ticks before moving on to the next task. It can also be given a `Duration` instead, or as well, for work that should be refined for a set amount of time, like progressive rendering or annealing, and it moves on once that much time has passed in the task, or once it's run all its iterations, whichever comes first. If neither is provided, it'll run forever. A compute task is also given a list of [ComputeStep]s, each of which is a specific shader to run, or other compute-related action to take, in order, each iteration. It can also be given an optional label, which is used to identify the task in the [ComputeTaskDoneEvent] that's thrown when the task completes.
+//!
+//! A task can also be given a [ComputeCondition], which lets data on the GPU decide when the task is done. This is useful for iterative algorithms that should keep going until they've converged, rather than for a fixed number of iterations. The condition names a small buffer, like a single `u32` flag or `f32` residual, and a predicate. At the end of every frame the buffer is copied back to the CPU, and once the data arrives, a frame or two later, it's passed to the predicate. Nothing ever waits on the GPU for it, so the task keeps going for a few iterations after the predicate is first satisfied. If the predicate returns true, the task ends. By default it then moves on to the next task, but the condition can also name the label of any other task in the sequence to jump to instead, which lets you build a state machine out of your tasks. If a task has a number of iterations or a duration as well as a condition, it ends as soon as any of them says it's done. The [ComputeTaskDoneEvent] says which one it was.
 //!
-//! Each [ComputeStep] contains just two fields.
+//! By default a task runs one iteration per frame, which can leave a long task with cheap iterations waiting on the framerate. Setting [iterations_per_frame](ComputeTask::iterations_per_frame) records that many iterations into each frame instead. Each iteration still gets its own value in the iteration buffer, its own schedule checks, and its own buffer swaps, but a condition is only checked once per frame, after the last of that frame's iterations, and a [CopyBuffer](ComputeAction::CopyBuffer) step only runs once per frame.
 //!
-//! The first is an optional maximum frequency. If provided, this means this step won't necessarily run every iteration, but only if it's been long enough since the last time it ran. The frequency is in Hz, or iterations per second. So if a max frequency of 30 is provided, that means if it's been less than 1000/30=16.67 ms since the last time it ran, then it won't run this iteration. This is often useful if you have a long running computation, and want to display the results in real time. You can potentially speed things up by only updating the display at a set framerate, even if the computation is running at a much faster rate.
+//! Most elements of a sequence are just tasks, wrapped in [Task](ComputeSequenceElement::Task), or turned into elements with `into()`. But multi-stage algorithms often run the same tasks over and over, like a multigrid V-cycle, or simulating 100 steps and then exporting the results, 50 times over. Rather than unrolling those into a huge list by hand, tasks can be grouped into loops. [Repeat](ComputeSequenceElement::Repeat) runs its elements a fixed number of times, [Loop](ComputeSequenceElement::Loop) runs them forever, and [LoopUntil](ComputeSequenceElement::LoopUntil) runs them until a [ComputeCondition] is satisfied, which is checked whenever the last task in the loop ends. Loops can be nested inside each other. Tasks are still numbered in the order they appear, counting into loops, and the [ComputeTaskDoneEvent] says how many times each loop the task is in has gone round, so a task that runs many times can still be told apart.
+//!
+//! Each [ComputeStep] contains two main fields, along with the buffers it reads and writes, which are only needed for ordering steps by their dependencies, as described below.
+//!
+//! The first is a [ComputeSchedule], which decides which iterations the step runs on. It can run on every iteration, on every Nth iteration, or only on the first or last iteration of the task. These are all based on the task's iteration count, so the same sequence always runs the same steps on the same iterations, however fast the machine is. It can also be given a maximum frequency, in which case the step only runs if it's been long enough since the last time it ran. The frequency is in Hz, or iterations per second. So if a max frequency of 30 is provided, that means if it's been less than 1000/30=16.67 ms since the last time it ran, then it won't run this iteration. This is often useful if you have a long running computation, and want to display the results in real time. You can potentially speed things up by only updating the display at a set framerate, even if the computation is running at a much faster rate. Since that's based on the wall clock, it's the one option that can run on different iterations from one run to the next.
 //!
 //! The second field of the [ComputeStep] is a [ComputeAction], which is an enum which describes what to actually do. It has the following options:
 //!
-//! - [RunShader](ComputeAction::RunShader) - The meat of the compute shaders. This runs an actual shader. You must provide the Bevy asset path to the shader file, the name of the entry point function in that shader file, and the workgroup count in the x, y and z dimensions.
+//! - [RunShader](ComputeAction::RunShader) - The meat of the compute shaders. This runs an actual shader. You must provide the Bevy asset path to the shader file, the name of the entry point function in that shader file, and a [DispatchSize] saying how many workgroups to run. That can be an explicit workgroup count in the x, y and z dimensions, or it can be worked out from the dimensions of a texture ([PerTexel](DispatchSize::PerTexel)) or the number of elements in a buffer ([PerElement](DispatchSize::PerElement)). In the latter cases, the workgroup size is read from the shader itself and the counts are rounded up, so your shader should check that its invocation is in bounds. It can also be given shader defs, override constants, push constants and the bind groups to use, which are described below.
+//! - [RunShaderIndirect](ComputeAction::RunShaderIndirect) - Just like [RunShader](ComputeAction::RunShader), except the workgroup counts are read from a GPU buffer when the shader is dispatched, so one shader can decide how much work the next one does without the counts ever coming back to the CPU.
 //! - [CopyBuffer](ComputeAction::CopyBuffer) - Copy the data from a buffer to the CPU. Will be returned as a `Vec<u8>` via a [CopyBufferEvent].
 //! - [SwapBuffers](ComputeAction::SwapBuffers) - Swap double buffers. See the "Double Buffering" section below.
+//! - [ClearBuffer](ComputeAction::ClearBuffer) - Set a buffer, or a range of bytes in it, to zero. This is recorded in order with the other steps, so it's how to reset an accumulator or an atomic counter between dispatches, without writing a shader just to clear it or setting the buffer from the CPU, which isn't ordered with the dispatches at all. The buffer needs the `COPY_DST` usage. For a double buffer, it can clear either the [Front](BufferSide::Front) or the [Back](BufferSide::Back) buffer.
+//! - [FillBuffer](ComputeAction::FillBuffer) - Like [ClearBuffer](ComputeAction::ClearBuffer), but fills the whole buffer with a repeating 4-byte value instead of zeroes.
+//!
+//! By default the steps of a task run in the order they're given, with each shader dispatched in its own compute pass. For long pipelines of small dispatches, a task can instead set its [step_order](ComputeTask::step_order) to [ByBufferAccess](ComputeStepOrder::ByBufferAccess), and have each step declare the buffers it [reads](ComputeStep::reads) and [writes](ComputeStep::writes). The steps are then ordered so that any two steps that use the same buffer, where at least one of them writes it, still run in the order they're given, and steps that don't depend on each other are batched together, with all the shaders in a batch dispatched in a single compute pass. If the declared accesses form a cycle, which can only happen through swaps, since they run after every other step that uses their buffer, the sequence fails with a [ComputeErrorEvent] naming the steps and buffers involved.
+//!
+//! The pipelines for every shader step in every task are queued to compile as soon as a sequence starts, so moving on to the next task doesn't have to wait for its pipelines to compile from scratch. Steps that would make identical pipelines, because they run the same entry point of the same shader with the same shader defs, override constants, push constant size and bind groups, share one. Normally each task starts as soon as its own pipelines are ready, but setting [wait_for_pipelines](StartComputeEvent::wait_for_pipelines) on the [StartComputeEvent] makes the sequence wait until every pipeline is ready before the first task starts, so it never stalls part way through.
+//!
+//! ### Shader Defs and Override Constants
+//!
+//! A shader step can be given shader defs, for the shader's `#ifdef`s and `#{NAME}` substitutions, and values for any constants the shader declares with `override`, so one shader file can be used with different feature toggles or constant values. Each distinct combination gets its own pipeline.
+//!
+//! Bevy's shader composer doesn't carry override constants through intact, so shaders that declare any are parsed directly by naga instead, and that means they can't `#import` anything, including the generated bindings module described above. A shader that needs its bindings imported should use shader defs for its constants instead, since they work with imports, or write its bindings out by hand. A shader that declares override constants and imports something anyway is turned away with a [ComputeErrorEvent] when its task starts.
+//!
+//! ### Push Constants
+//!
+//! A shader step can also be given bytes to set as push constants, which are a cheap way to pass a few small parameters, like a pass index, to a single dispatch. These need the `PUSH_CONSTANTS` feature, which isn't available everywhere, notably on WebGPU. If a step uses them on a device without it, or uses more than the device allows, the sequence is ended with a [ComputeErrorEvent] followed by the [Failed](ComputeSequenceOutcome::Failed) [ComputeSequenceDoneEvent].
+//!
+//! ### Bind Groups
+//!
+//! By default every bind group in the [ShaderBufferSet] is bound to every shader, at its own group index, so every shader has to be compatible with all of them. A shader step can instead name just the groups it uses, and the order they're bound in, so unrelated shaders can share one [ShaderBufferSet] without needing compatible layouts.
+//!
+//! ### Hot Reloading
+//!
+//! Shaders are hot reloaded. If Bevy's asset file watching is turned on, with its `file_watcher` feature, then editing a shader file while a sequence is running recompiles the pipelines that use it, and they're swapped in between iterations, leaving the contents of all the buffers alone. If the new version fails to compile, a warning is logged and the last version that worked keeps running.
+//!
+//! ## Builders
+//!
+//! Filling in every field of these structs gets long, so [ComputeTask], [ComputeStep] and [StartComputeEvent] can also be built up one setting at a time. The functions that add a step to a task, like [run](ComputeTask::run) and [swap](ComputeTask::swap), add it with default settings, and the ones that change a step's settings, like [every](ComputeTask::every) and [shader_def](ComputeTask::shader_def), change the last step that was added. `every` takes either a number of iterations or a frequency, written with the [Hz] trait. Anything that doesn't make sense, like zero iterations, a task with no steps, or shader defs on a step that doesn't run a shader, panics as soon as it's given, rather than when the sequence runs. For example, the Game of Life example starts its sequence like this:
+//!
+//! ```Rust
+//! start_compute_events.write(
+//!     StartComputeEvent::new(ComputeSequenceId(0))
+//!         .task(
+//!             ComputeTask::new("Init")
+//!                 .iterations(1)
+//!                 .run(SHADER_ASSET_PATH, "init", DispatchSize::PerTexel(image))
+//!                 .swap(image),
+//!         )
+//!         .task(
+//!             ComputeTask::new("Update")
+//!                 .forever()
+//!                 .run(SHADER_ASSET_PATH, "update", DispatchSize::PerTexel(image))
+//!                 .every(10.hz())
+//!                 .swap(image)
+//!                 .every(10.hz()),
+//!         ),
+//! );
+//! ```
+//!
+//! Loops can be made the same way, with [repeat](ComputeSequenceElement::repeat), [forever](ComputeSequenceElement::forever) and [until](ComputeSequenceElement::until).
+//!
+//! ## Loading Sequences from Files
+//!
+//! Sequences don't have to be written in Rust. A [ComputeSequenceAsset] describes one in a `.compute.ron` file, which can be loaded with the [AssetServer], and started with a [StartComputeAssetEvent]. The file has the same structure as a [StartComputeEvent], except that buffers are referred to by name, which you give them with [name_buffer](ShaderBufferSet::name_buffer), and durations are given in seconds. Task conditions are Rust closures, so they can't be written in a file. For example, the Game of Life example could be written as:
+//!
+//! ```ron
+//! #![enable(implicit_some)]
+//! (
+//!     tasks: [
+//!         Task((
+//!             label: "Init",
+//!             iterations: 1,
+//!             steps: [
+//!                 (action: RunShader(shader: "shaders/game_of_life.wgsl", entry_point: "init", dispatch: PerTexel("cells"))),
+//!                 (action: SwapBuffers(buffer: "cells")),
+//!             ],
+//!         )),
+//!         Task((
+//!             label: "Update",
+//!             steps: [
+//!                 (schedule: MaxFrequency(10), action: RunShader(shader: "shaders/game_of_life.wgsl", entry_point: "update", dispatch: PerTexel("cells"))),
+//!                 (schedule: MaxFrequency(10), action: SwapBuffers(buffer: "cells")),
+//!             ],
+//!         )),
+//!     ],
+//! )
+//! ```
+//!
+//! If Bevy's asset file watching is turned on, then saving changes to the file while the sequence is running restarts it with the new version, so task lists, iteration counts and workgroup counts can be tweaked without recompiling. If the new version names a buffer that doesn't exist, a warning is logged and the old version keeps running.
+//!
+//! # Controlling a Running Sequence
+//!
+//! Once a sequence has started, it can be controlled with a few more events, each of which takes the [ComputeSequenceId] of the sequence to control. All of them take effect at the end of the current iteration, so an iteration is never interrupted part way through.
+//!
+//! - [PauseComputeEvent] - Pause the sequence. Nothing runs until it's resumed, and the time spent paused doesn't count towards the current task's [time_in_group](ComputeTaskDoneEvent::time_in_group).
+//! - [ResumeComputeEvent] - Resume a paused sequence right where it left off.
+//! - [StopComputeEvent] - Stop the sequence for good. Anything set up for the current task, like the intermediate buffers used by [CopyBuffer](ComputeAction::CopyBuffer), is released.
+//!
+//! When a sequence ends, whether because every task completed or because it was stopped, a [ComputeSequenceDoneEvent] is thrown. Its [outcome](ComputeSequenceDoneEvent::outcome) tells you which of those happened.
+//!
+//! If something goes wrong while a sequence is running, like a shader that can't be found, doesn't compile, or takes longer than the [pipeline_timeout](StartComputeEvent::pipeline_timeout) for its pipeline to be ready, which is 30 seconds unless it's changed, or a buffer it uses being deleted, the sequence is ended rather than bringing down the app. A [ComputeErrorEvent] is thrown describing what went wrong, including which task and step it happened in, the shader and entry point involved, and any error text from naga or wgpu. It's followed by a [ComputeSequenceDoneEvent] with a [Failed](ComputeSequenceOutcome::Failed) outcome.
+//!
+//! Mistakes in how a sequence uses its buffers, like swapping a buffer that isn't a double buffer, copying a texture back to the CPU, copying the same buffer back twice in one task, giving a condition a buffer that isn't the size its predicate reads, or using a buffer that's been deleted, are caught before the sequence even starts. Every [StartComputeEvent] is checked against the [ShaderBufferSet], and if anything's wrong, a [ComputeErrorEvent] is thrown for each problem, followed by a [ComputeSequenceDoneEvent] with a [Rejected](ComputeSequenceOutcome::Rejected) outcome, and the sequence never runs. If there's already a sequence running with the same id, it's left running, so the rejection doesn't mean it's over. The same checks can be run ahead of time with [validate_sequence](ShaderBufferSet::validate_sequence).
+//!
+//! To keep an eye on a sequence while it runs, the [ComputeStatus] resource holds the current task, how many of its iterations have run, the number of iterations per second, the time spent in the task and whether it's still waiting for pipelines to compile, for every sequence that's running. That's enough to draw a progress bar for a task with a fixed number of iterations, or to check that one without is still moving. If you'd rather be told, give the [StartComputeEvent] a [progress_interval](StartComputeEvent::progress_interval), and a [ComputeProgressEvent] carrying the same information will be thrown that often.
+//!
+//! ## Running Multiple Sequences
+//!
+//! Any number of sequences can run at the same time, each with its own list of tasks and its own lifetime, as long as they each have a different [ComputeSequenceId]. Every [ComputeTaskDoneEvent], [CopyBufferEvent] and [ComputeSequenceDoneEvent] carries the id of the sequence it came from, so you can tell them apart. Sequences all share the same [ShaderBufferSet], so they can work on the same buffers, but there are no guarantees about the order they run in relative to each other within a frame.
+//!
+//! Once a sequence is done, its id is free to be used again, so you can start a fresh list of tasks whenever the old one completes. You can also start a new sequence with the id of one that's still running, in which case the old sequence is replaced at the end of its current iteration, and a [ComputeSequenceDoneEvent] is thrown for it with a [Replaced](ComputeSequenceOutcome::Replaced) outcome. Either way, the new sequence starts from its first task, and the contents of the buffers are left as they were.
 //!
 //! # Double Buffering
 //!
@@ -78,29 +213,47 @@ mod compute_main_setup;
 mod compute_node;
 mod compute_render_setup;
 mod compute_sequence;
+mod compute_sequence_asset;
 mod extract_resources;
 mod parse_render_messages;
 mod queue_bind_group;
 mod shader_buffer_set;
+mod shader_reflection;
+mod step_graph;
 mod swap_sprite_buffers;
+#[cfg(test)]
+mod test_fixtures;
+mod wgsl_bindings;
+mod wgsl_types;
 
-use std::{sync::mpsc::sync_channel, time::Duration};
+use std::{sync::mpsc::channel, time::Duration};
 
 use bevy::{
 	prelude::*,
 	render::{Render, RenderApp, RenderSet},
 };
+use bevy_platform::collections::hash_map::HashMap;
 use compute_data_transmission::ComputeDataTransmission;
-use compute_main_setup::compute_main_setup;
+use compute_main_setup::{compute_main_control, compute_main_setup};
 use compute_render_setup::compute_render_setup;
-use compute_sequence::ComputeSequence;
+use compute_sequence::ComputeSequences;
 pub use compute_sequence::*;
+use compute_sequence_asset::{start_compute_assets, ComputeSequenceAssetLoader, ComputeSequenceAssets};
+pub use compute_sequence_asset::{
+	ComputeActionDescription, ComputeElementDescription, ComputeSequenceAsset, ComputeSequenceAssetError,
+	ComputeStepDescription, ComputeTaskDescription, DispatchSizeDescription, ShaderDefDescription,
+	StartComputeAssetEvent,
+};
 use extract_resources::extract_resources;
 use parse_render_messages::parse_render_messages;
 use queue_bind_group::queue_bind_group;
 use shader_buffer_set::ShaderBufferSetPlugin;
 pub use shader_buffer_set::*;
+use shader_reflection::{reflect_shaders, ShaderReflections};
 use swap_sprite_buffers::swap_sprite_buffers;
+use wgsl_bindings::update_bindings_shader;
+pub use wgsl_bindings::BINDINGS_IMPORT_PATH;
+pub use wgsl_types::{WgslStruct, WgslType};
 
 /// This plugin adds all the systems, resources and events necessary for bevy_compute to function. Please add it to your
 /// bevy app with:
@@ -112,39 +265,141 @@ pub struct BevyComputePlugin;
 
 impl Plugin for BevyComputePlugin {
 	fn build(&self, app: &mut App) {
-		let (sender, receiver) = sync_channel(16);
+		// The channel is unbounded, since without pipelined rendering the main world can't empty it until the render
+		// world is done with the frame, so a full channel would block the render world forever.
+		let (sender, receiver) = channel();
 
 		app
 			.add_plugins(ShaderBufferSetPlugin)
 			.insert_non_send_resource(ComputeDataTransmission { sender, receiver })
-			.add_systems(Update, compute_main_setup)
-			.add_systems(First, parse_render_messages.run_if(resource_exists::<ComputeSequence>))
-			.add_systems(Update, swap_sprite_buffers.run_if(resource_exists::<ComputeSequence>))
+			.insert_resource(ComputeSequences(HashMap::new()))
+			.init_resource::<ShaderReflections>()
+			.init_resource::<ComputeStatus>()
+			.init_resource::<ComputeSequenceAssets>()
+			.init_asset::<ComputeSequenceAsset>()
+			.init_asset_loader::<ComputeSequenceAssetLoader>()
+			.add_systems(
+				Update,
+				(start_compute_assets, compute_main_setup, compute_main_control, update_bindings_shader, reflect_shaders)
+					.chain(),
+			)
+			.add_systems(First, parse_render_messages)
+			.add_systems(Update, swap_sprite_buffers)
 			.add_event::<StartComputeEvent>()
+			.add_event::<StartComputeAssetEvent>()
+			.add_event::<StopComputeEvent>()
+			.add_event::<PauseComputeEvent>()
+			.add_event::<ResumeComputeEvent>()
 			.add_event::<CopyBufferEvent>()
-			.add_event::<ComputeTaskDoneEvent>();
+			.add_event::<ComputeTaskDoneEvent>()
+			.add_event::<ComputeSequenceDoneEvent>()
+			.add_event::<ComputeErrorEvent>()
+			.add_event::<ComputeProgressEvent>();
 
 		let render_app = app.sub_app_mut(RenderApp);
 		render_app
-			.add_systems(ExtractSchedule, extract_resources)
-			.add_systems(Render, queue_bind_group.in_set(RenderSet::Queue).run_if(resource_exists::<ComputeSequence>))
-			.add_systems(Render, compute_render_setup.run_if(resource_added::<ComputeSequence>));
+			.add_systems(ExtractSchedule, (extract_resources::<ComputeSequences>, extract_resources::<ShaderReflections>))
+			.add_systems(Render, queue_bind_group.in_set(RenderSet::Queue).run_if(resource_exists::<ComputeSequences>))
+			.add_systems(Render, compute_render_setup.run_if(resource_exists_and_changed::<ComputeSequences>));
 	}
 }
 
-/// This event is how you start the compute shaders. Specify the details of how they're going to run with the [tasks](StartComputeEvent::tasks), and optionally provide a buffer to store the current iteration count with [iteration_buffer](StartComputeEvent::iteration_buffer).
+/// This event is how you start the compute shaders. Give the sequence an [id](StartComputeEvent::id), specify the details of how it's going to run with the [tasks](StartComputeEvent::tasks), and optionally provide a buffer to store the current iteration count with [iteration_buffer](StartComputeEvent::iteration_buffer).
 #[derive(Event)]
 pub struct StartComputeEvent {
-	/// Ths list of compute tasks to complete. It will run each task in sequence, and throw a [ComputeTaskDoneEvent] when they're done.
-	pub tasks: Vec<ComputeTask>,
+	/// The id of this sequence. Every event thrown by this sequence will carry it, and it's how you refer to this sequence when stopping, pausing or resuming it. If there's already a sequence running with this id, it will be replaced by this one.
+	pub id: ComputeSequenceId,
+
+	/// The list of compute tasks to complete. It will run each task in sequence, and throw a [ComputeTaskDoneEvent] when they're done. Tasks can also be grouped into loops, as described by [ComputeSequenceElement]. A plain [ComputeTask] can be turned into an element with `into()`.
+	pub tasks: Vec<ComputeSequenceElement>,
 
 	/// An optional iteration buffer. This buffer should be a 4-byte uniform buffer, that stores a single u32. If provided, then every tick, it will be set to the current iteration count within the current compute task. It will reset to zero every time a new compute task starts.
 	pub iteration_buffer: Option<ShaderBufferHandle>,
+
+	/// Whether to wait for the pipelines of every task to be ready before starting the first one. The pipelines for the whole sequence are always queued to compile as soon as it starts, but normally the first task starts as soon as its own pipelines are ready, and later tasks may still have to wait for theirs. Waiting for all of them up front means the sequence never stalls part way through. If any of them fails to compile, the sequence fails before anything runs.
+	pub wait_for_pipelines: bool,
+
+	/// How often to throw a [ComputeProgressEvent] while the sequence runs. If this isn't provided, none are thrown, but the sequence's progress can still be checked at any time through the [ComputeStatus] resource.
+	pub progress_interval: Option<Duration>,
+
+	/// How long a task's pipelines, or with [wait_for_pipelines](StartComputeEvent::wait_for_pipelines), all of the sequence's pipelines, can take to be ready before the sequence fails, in case they never will be. It's measured from when they're queued, leaving out any time spent paused. [new](StartComputeEvent::new) sets this to [DEFAULT_PIPELINE_TIMEOUT], but shaders that take a long time to compile on slow machines may need longer.
+	pub pipeline_timeout: Duration,
+}
+
+/// How long a sequence's pipelines can take to be ready by default. See [StartComputeEvent::pipeline_timeout].
+pub const DEFAULT_PIPELINE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// These functions build the event up one setting at a time, starting with no tasks, no iteration buffer, no waiting for pipelines, no progress events and the [default pipeline timeout](DEFAULT_PIPELINE_TIMEOUT).
+impl StartComputeEvent {
+	/// Start building an event that starts a sequence with the given id.
+	pub fn new(id: ComputeSequenceId) -> Self {
+		Self {
+			id,
+			tasks: Vec::new(),
+			iteration_buffer: None,
+			wait_for_pipelines: false,
+			progress_interval: None,
+			pipeline_timeout: DEFAULT_PIPELINE_TIMEOUT,
+		}
+	}
+
+	/// Add a task, or a loop of tasks, to the end of the sequence. Panics if it's a task with no steps, or a loop with nothing in it.
+	pub fn task(mut self, task: impl Into<ComputeSequenceElement>) -> Self {
+		let task = task.into();
+		task.validate();
+		self.tasks.push(task);
+		self
+	}
+
+	/// Set the iteration buffer.
+	pub fn iteration_buffer(mut self, buffer: ShaderBufferHandle) -> Self {
+		self.iteration_buffer = Some(buffer);
+		self
+	}
+
+	/// Wait for the pipelines of every task to be ready before starting the first one.
+	pub fn wait_for_pipelines(mut self) -> Self {
+		self.wait_for_pipelines = true;
+		self
+	}
+
+	/// Throw a [ComputeProgressEvent] this often. Panics if it's zero.
+	pub fn progress_interval(mut self, interval: Duration) -> Self {
+		if interval.is_zero() {
+			panic!("Tried to throw progress events with an interval of zero");
+		}
+		self.progress_interval = Some(interval);
+		self
+	}
+
+	/// Set how long pipelines can take to be ready before the sequence fails. Panics if it's zero.
+	pub fn pipeline_timeout(mut self, timeout: Duration) -> Self {
+		if timeout.is_zero() {
+			panic!("Tried to give pipelines a timeout of zero");
+		}
+		self.pipeline_timeout = timeout;
+		self
+	}
 }
 
+/// This event stops the compute sequence with the given id. It takes effect at the end of the current iteration, at which point any per-task state, like the intermediate buffers used by [CopyBuffer](ComputeAction::CopyBuffer), is released and a [ComputeSequenceDoneEvent] is thrown with a [Cancelled](ComputeSequenceOutcome::Cancelled) outcome. If the sequence has already finished, this does nothing.
+#[derive(Event)]
+pub struct StopComputeEvent(pub ComputeSequenceId);
+
+/// This event pauses the compute sequence with the given id at the end of the current iteration. Nothing will run until a [ResumeComputeEvent] is sent. Time spent paused doesn't count towards the [time_in_group](ComputeTaskDoneEvent::time_in_group) of the current task.
+#[derive(Event)]
+pub struct PauseComputeEvent(pub ComputeSequenceId);
+
+/// This event resumes the compute sequence with the given id, after it was paused with a [PauseComputeEvent]. It picks up on the next iteration right where it left off.
+#[derive(Event)]
+pub struct ResumeComputeEvent(pub ComputeSequenceId);
+
 /// This event is thrown every time a [CopyBuffer][ComputeAction::CopyBuffer] compute action is executed. It contains the handle of the buffer that was copied, and a `Vec<u8>` with all the data. This is how you get data back out of the compute shader to the CPU.
 #[derive(Event)]
 pub struct CopyBufferEvent {
+	/// The id of the sequence that copied the buffer.
+	pub sequence: ComputeSequenceId,
+
 	/// This is the handle of the buffer that was copied.
 	pub buffer: ShaderBufferHandle,
 
@@ -155,7 +410,10 @@ pub struct CopyBufferEvent {
 /// This event is thrown every time a compute task is completed.
 #[derive(Event)]
 pub struct ComputeTaskDoneEvent {
-	/// The number of the completed task, as in, the index into the `Vec<ComputeTask>` that was provided in the [StartComputeEvent].
+	/// The id of the sequence the task belongs to.
+	pub sequence: ComputeSequenceId,
+
+	/// The number of the completed task, as in, its index among all the tasks in the [StartComputeEvent], counting into loops. See [ComputeSequenceElement] for how that works.
 	pub group_finished: usize,
 
 	/// The label of the completed task, if one was provided.
@@ -166,6 +424,123 @@ pub struct ComputeTaskDoneEvent {
 
 	/// Whether this is the final task. If all you care about is whether the entire compute sequence is done, then check this.
 	pub final_group: bool,
+
+	/// What ended the task.
+	pub reason: ComputeTaskEndReason,
+
+	/// How many times each loop the task is in had gone round when it ended, counting from zero, starting with the outermost loop. This is empty if the task isn't in a loop.
+	pub loop_iterations: Vec<u32>,
+}
+
+/// Describes what ended a compute task, as reported by a [ComputeTaskDoneEvent].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ComputeTaskEndReason {
+	/// The task ran all of its [iterations](ComputeTask::iterations).
+	Iterations,
+
+	/// The task ran for its whole [duration](ComputeTask::duration).
+	Duration,
+
+	/// The task's [condition](ComputeTask::condition) was satisfied.
+	Condition,
+}
+
+/// This event is thrown once when a compute sequence is over, whether that's because every task ran to completion, because it was stopped with a [StopComputeEvent], because it was replaced by a new sequence with the same id, or because it failed. It's also thrown for a sequence that was turned away before it started, with a [Rejected](ComputeSequenceOutcome::Rejected) outcome. That doesn't mean a sequence already running with the same id has ended, since it's left running, and gets its own event when it does end.
+#[derive(Event)]
+pub struct ComputeSequenceDoneEvent {
+	/// The id of the sequence that ended.
+	pub sequence: ComputeSequenceId,
+
+	/// How the sequence ended.
+	pub outcome: ComputeSequenceOutcome,
+}
+
+/// Describes how a compute sequence ended, as reported by a [ComputeSequenceDoneEvent].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ComputeSequenceOutcome {
+	/// Every task in the sequence ran to completion.
+	Finished,
+
+	/// The sequence was stopped early with a [StopComputeEvent].
+	Cancelled,
+
+	/// The sequence was replaced part way through, by another [StartComputeEvent] with the same id.
+	Replaced,
+
+	/// Something went wrong while running the sequence, which was reported with a [ComputeErrorEvent].
+	Failed,
+
+	/// The sequence never started, because something was wrong with it, which was reported with a [ComputeErrorEvent]. Any sequence that was already running with the same id is left running.
+	Rejected,
+}
+
+/// This event is thrown when something goes wrong with a compute sequence, like a shader failing to load or compile, or a step using a buffer in a way it can't be used. The sequence is ended when this happens, and a [ComputeSequenceDoneEvent] with a [Failed](ComputeSequenceOutcome::Failed) outcome follows this event. If the problem was found before the sequence started, it never starts, and the outcome is [Rejected](ComputeSequenceOutcome::Rejected) instead.
+#[derive(Event, Debug)]
+pub struct ComputeErrorEvent {
+	/// The id of the sequence that failed.
+	pub sequence: ComputeSequenceId,
+
+	/// The index of the task that was running when it failed.
+	pub task: usize,
+
+	/// The index of the step within that task that caused the failure, if it was caused by a particular step.
+	pub step: Option<usize>,
+
+	/// The Bevy asset path of the shader the step runs, if it runs one.
+	pub shader: Option<String>,
+
+	/// The name of the entry point the step runs, if it runs a shader.
+	pub entry_point: Option<String>,
+
+	/// A description of what went wrong, including any error text from naga or wgpu.
+	pub error: String,
+}
+
+/// This resource holds the latest status of every compute sequence that's running, keyed by its id. The render world reports it every frame, so it's at most a frame or two behind. A sequence appears here once it has started running, and is removed once it's done.
+#[derive(Resource, Default)]
+pub struct ComputeStatus(HashMap<ComputeSequenceId, ComputeSequenceStatus>);
+
+impl ComputeStatus {
+	/// Gets the status of the sequence with the given id, if it's running.
+	pub fn get(&self, sequence: ComputeSequenceId) -> Option<&ComputeSequenceStatus> { self.0.get(&sequence) }
+
+	/// Iterates over the status of every sequence that's running.
+	pub fn iter(&self) -> impl Iterator<Item = (&ComputeSequenceId, &ComputeSequenceStatus)> { self.0.iter() }
+}
+
+/// How a running compute sequence is getting on, as held by the [ComputeStatus] resource and thrown in a [ComputeProgressEvent].
+#[derive(Clone, Debug)]
+pub struct ComputeSequenceStatus {
+	/// The index of the task that's running, among all the tasks in the [StartComputeEvent], counting into loops. See [ComputeSequenceElement] for how that works.
+	pub task: usize,
+
+	/// How many times each loop the task is in has gone round, counting from zero, starting with the outermost loop. This is empty if the task isn't in a loop.
+	pub loop_iterations: Vec<u32>,
+
+	/// The label of the task that's running, if one was provided.
+	pub task_label: Option<String>,
+
+	/// The number of iterations of the current task that have run. Along with the task's [iterations](ComputeTask::iterations), this is enough for a progress bar.
+	pub iterations: u32,
+
+	/// The number of iterations run per second, worked out once a second from the iterations run since the last time. It drops to zero while the sequence is paused or waiting for pipelines.
+	pub iterations_per_second: f32,
+
+	/// The time spent on the current task so far, not counting time spent paused.
+	pub time_in_task: Duration,
+
+	/// Whether the sequence is waiting for pipelines to compile before it can run the current task.
+	pub compiling_pipelines: bool,
+}
+
+/// This event is thrown periodically while a compute sequence runs, if it was started with a [progress_interval](StartComputeEvent::progress_interval).
+#[derive(Event, Clone, Debug)]
+pub struct ComputeProgressEvent {
+	/// The id of the sequence.
+	pub sequence: ComputeSequenceId,
+
+	/// How the sequence is getting on.
+	pub status: ComputeSequenceStatus,
 }
 
 /// This component should be placed on any sprite entity that is intended to display a double buffered texture. It requires a [Sprite]. There is an internal system that will update the image handle on that [Sprite] to be the current front buffer.
@@ -2,25 +2,50 @@ use bevy::prelude::*;
 
 use super::{
 	compute_data_transmission::{ComputeDataTransmission, ComputeMessage},
-	ComputeTaskDoneEvent, CopyBufferEvent,
+	compute_sequence::ComputeSequences,
+	ComputeErrorEvent, ComputeProgressEvent, ComputeSequenceDoneEvent, ComputeStatus, ComputeTaskDoneEvent,
+	CopyBufferEvent,
 };
 use crate::shader_buffer_set::ShaderBufferSet;
 
+#[allow(clippy::too_many_arguments)]
 pub fn parse_render_messages(
 	mut copy_buffer_events: EventWriter<CopyBufferEvent>, mut group_done_events: EventWriter<ComputeTaskDoneEvent>,
-	mut buffer_set: ResMut<ShaderBufferSet>, transmission: NonSend<ComputeDataTransmission>,
+	mut sequence_done_events: EventWriter<ComputeSequenceDoneEvent>, mut error_events: EventWriter<ComputeErrorEvent>,
+	mut progress_events: EventWriter<ComputeProgressEvent>, mut buffer_set: ResMut<ShaderBufferSet>,
+	mut sequences: ResMut<ComputeSequences>, mut status: ResMut<ComputeStatus>,
+	transmission: NonSend<ComputeDataTransmission>,
 ) {
 	while let Ok(data) = transmission.receiver.try_recv() {
 		match data {
 			ComputeMessage::CopyBuffer(event) => {
-				copy_buffer_events.send(event);
+				copy_buffer_events.write(event);
 			}
 			ComputeMessage::GroupDone(event) => {
-				group_done_events.send(event);
+				group_done_events.write(event);
+			}
+			ComputeMessage::SequenceDone(event, generation) => {
+				if sequences.0.get(&event.sequence).is_some_and(|sequence| sequence.generation == generation) {
+					sequences.0.remove(&event.sequence);
+					status.0.remove(&event.sequence);
+				}
+				sequence_done_events.write(event);
 			}
 			ComputeMessage::SwapBuffers(handle) => {
 				buffer_set.swap_front_buffer(handle);
 			}
+			ComputeMessage::Error(event) => {
+				error_events.write(event);
+			}
+			ComputeMessage::Progress(event) => {
+				progress_events.write(event);
+			}
+		}
+	}
+
+	for (id, sequence) in sequences.0.iter() {
+		if let Some(sequence_status) = sequence.status.lock().unwrap().take() {
+			status.0.insert(*id, sequence_status);
 		}
 	}
 }
@@ -1,6 +1,8 @@
 use std::{
+	collections::VecDeque,
 	fmt::{Display, Formatter},
-	sync::mpsc::channel,
+	ops::Range,
+	sync::{Arc, Mutex},
 };
 
 use bevy::{
@@ -11,17 +13,28 @@ use bevy::{
 		render_resource::{
 			encase::private::{WriteInto, Writer},
 			BindGroup, BindGroupEntry, BindGroupLayout, BindGroupLayoutEntry, BindingResource, BindingType, Buffer,
-			BufferBindingType, BufferDescriptor, BufferInitDescriptor, BufferUsages, Extent3d, Maintain, MapMode,
-			ShaderStages, ShaderType, StorageBuffer, StorageTextureAccess, TextureDimension, TextureFormat, TextureUsages,
-			TextureViewDimension,
+			BufferAsyncError, BufferBindingType, BufferDescriptor, BufferId, BufferInitDescriptor, BufferUsages, Extent3d,
+			MapMode, ShaderStages, ShaderType, StorageBuffer, StorageTextureAccess, TextureDimension, TextureFormat,
+			TextureUsages, TextureViewDimension, TextureViewId,
 		},
-		renderer::{RenderContext, RenderDevice, RenderQueue},
+		renderer::{RenderDevice, RenderQueue},
 		texture::GpuImage,
 		Extract, RenderApp,
 	},
 };
 use bevy_platform::collections::hash_map::HashMap;
 
+use crate::{
+	compute_sequence::{
+		flatten_elements, BufferSide, ComputeAction, ComputeCondition, ComputeLoop, ComputeLoopKind,
+		ComputeSequenceElement, ComputeSequenceId, ComputeStep, ComputeTask, DispatchSize,
+	},
+	wgsl_types::{check_struct_layouts, check_struct_names, WgslStruct, WgslType},
+};
+
+// The size of one set of indirect dispatch arguments, which is three u32 workgroup counts.
+pub(crate) const INDIRECT_ARGS_SIZE: u64 = 12;
+
 #[derive(Clone)]
 enum ShaderBufferStorage {
 	Storage { buffer: Buffer, readonly: bool },
@@ -29,7 +42,25 @@ enum ShaderBufferStorage {
 	StorageTexture { format: TextureFormat, access: StorageTextureAccess, image: Handle<Image> },
 }
 
+// The resource actually bound in one place, which is all that decides what a bind group holds.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub(crate) enum BoundId {
+	Buffer(BufferId),
+	Texture(Option<TextureViewId>),
+}
+
 impl ShaderBufferStorage {
+	fn bound_id(&self, gpu_images: &RenderAssets<GpuImage>) -> BoundId {
+		match self {
+			ShaderBufferStorage::Storage { buffer, .. } | ShaderBufferStorage::Uniform(buffer) => {
+				BoundId::Buffer(buffer.id())
+			}
+			ShaderBufferStorage::StorageTexture { image, .. } => {
+				BoundId::Texture(gpu_images.get(image).map(|image| image.texture_view.id()))
+			}
+		}
+	}
+
 	fn bind_group_entry<'a>(&'a self, binding: u32, gpu_images: &'a RenderAssets<GpuImage>) -> BindGroupEntry<'a> {
 		match self {
 			ShaderBufferStorage::Storage { buffer, readonly: _ } => {
@@ -78,6 +109,18 @@ impl ShaderBufferStorage {
 		}
 	}
 
+	fn bound_resource(&self, access_override: Option<StorageTextureAccess>) -> BoundResource {
+		match self {
+			ShaderBufferStorage::Storage { buffer, readonly } => {
+				BoundResource::Storage { read_only: *readonly, size: buffer.size() }
+			}
+			ShaderBufferStorage::Uniform(buffer) => BoundResource::Uniform { size: buffer.size() },
+			ShaderBufferStorage::StorageTexture { format, access, .. } => {
+				BoundResource::StorageTexture { format: *format, access: access_override.unwrap_or(*access) }
+			}
+		}
+	}
+
 	pub fn delete(&mut self, images: &mut Assets<Image>) {
 		match &self {
 			ShaderBufferStorage::Storage { buffer, .. } => buffer.destroy(),
@@ -104,6 +147,32 @@ impl ShaderBufferStorage {
 	}
 }
 
+// What a buffer is bound as, so it can be checked against what a shader declares at the same binding.
+pub(crate) enum BoundResource {
+	Storage { read_only: bool, size: u64 },
+	Uniform { size: u64 },
+	StorageTexture { format: TextureFormat, access: StorageTextureAccess },
+}
+
+impl BoundResource {
+	pub fn kind(&self) -> &'static str {
+		match self {
+			BoundResource::Storage { .. } => "a storage buffer",
+			BoundResource::Uniform { .. } => "a uniform buffer",
+			BoundResource::StorageTexture { .. } => "a storage texture",
+		}
+	}
+}
+
+// A binding of a named buffer, as a shader would declare it.
+pub(crate) struct NamedBinding {
+	pub name: String,
+	pub group: u32,
+	pub binding: u32,
+	pub resource: BoundResource,
+	pub wgsl_type: Option<BufferType>,
+}
+
 #[derive(Clone, Copy, PartialEq, Eq)]
 enum FrontBuffer {
 	First,
@@ -170,6 +239,17 @@ impl ShaderBufferInfo {
 		})
 	}
 
+	fn new_indirect_args(render_device: &RenderDevice, args: &[[u32; 3]], binding: Binding) -> Self {
+		Self::new(binding, || ShaderBufferStorage::Storage {
+			buffer: render_device.create_buffer_with_data(&BufferInitDescriptor {
+				label: None,
+				contents: &args.iter().flatten().flat_map(|count| count.to_le_bytes()).collect::<Vec<_>>(),
+				usage: BufferUsages::STORAGE | BufferUsages::INDIRECT | BufferUsages::COPY_SRC | BufferUsages::COPY_DST,
+			}),
+			readonly: false,
+		})
+	}
+
 	fn new_storage_init<T: ShaderType + WriteInto + Default + Clone>(
 		render_device: &RenderDevice, render_queue: &RenderQueue, data: T, usage: BufferUsages, binding: Binding,
 		readonly: bool,
@@ -179,7 +259,7 @@ impl ShaderBufferInfo {
 				let mut buffer = StorageBuffer::default();
 				buffer.set(data.clone());
 				buffer.add_usages(usage);
-				buffer.write_buffer(&render_device, &render_queue);
+				buffer.write_buffer(render_device, render_queue);
 				buffer.buffer().unwrap().clone()
 			},
 			readonly,
@@ -194,7 +274,7 @@ impl ShaderBufferInfo {
 				let mut buffer = StorageBuffer::default();
 				buffer.set(data.clone());
 				buffer.add_usages(usage);
-				buffer.write_buffer(&render_device, &render_queue);
+				buffer.write_buffer(render_device, render_queue);
 				buffer.buffer().unwrap().clone()
 			})
 		})
@@ -206,7 +286,7 @@ impl ShaderBufferInfo {
 	) -> Self {
 		Self::new(binding, || {
 			let mut image = Image::new_fill(
-				Extent3d { width: width, height: height, depth_or_array_layers: 1 },
+				Extent3d { width, height, depth_or_array_layers: 1 },
 				TextureDimension::D2,
 				fill,
 				format,
@@ -221,18 +301,30 @@ impl ShaderBufferInfo {
 		})
 	}
 
-	fn bind_group_entries<'a>(&'a self, gpu_images: &'a RenderAssets<GpuImage>) -> Vec<BindGroupEntry<'a>> {
+	// The storage bound at each of the buffer's bindings, with a double buffer's front and back switched around if it's
+	// been swapped part way through a frame.
+	fn bound_storage(&self, swapped: bool) -> Vec<(u32, &ShaderBufferStorage)> {
 		match self {
-			Self::SingleBound { binding: (_, binding), storage } => vec![storage.bind_group_entry(*binding, gpu_images)],
+			Self::SingleBound { binding: (_, binding), storage } => vec![(*binding, storage)],
 			Self::SingleUnbound { .. } => vec![],
 			Self::Double { binding: (_, (binding1, binding2)), storage: (storage1, storage2), front } => {
 				let (storage1, storage2) =
-					if *front == FrontBuffer::First { (storage2, storage1) } else { (storage1, storage2) };
-				vec![storage1.bind_group_entry(*binding1, gpu_images), storage2.bind_group_entry(*binding2, gpu_images)]
+					if (*front == FrontBuffer::First) != swapped { (storage2, storage1) } else { (storage1, storage2) };
+				vec![(*binding1, storage1), (*binding2, storage2)]
 			}
 		}
 	}
 
+	fn bind_group_entries<'a>(
+		&'a self, swapped: bool, gpu_images: &'a RenderAssets<GpuImage>,
+	) -> Vec<BindGroupEntry<'a>> {
+		self
+			.bound_storage(swapped)
+			.into_iter()
+			.map(|(binding, storage)| storage.bind_group_entry(binding, gpu_images))
+			.collect()
+	}
+
 	fn bind_group_layout_entry(&self) -> Vec<BindGroupLayoutEntry> {
 		match &self {
 			&ShaderBufferInfo::SingleBound { binding: (_, binding), storage } => vec![BindGroupLayoutEntry {
@@ -323,10 +415,22 @@ pub struct ShaderBufferSet {
 	buffers: HashMap<u32, ShaderBufferInfo>,
 	groups: Vec<Vec<u32>>,
 	next_id: u32,
+	// Names given to buffers, so they can be referred to from sequences loaded from files, and from shaders that import
+	// the generated bindings.
+	names: HashMap<String, ShaderBufferHandle>,
+	// The WGSL types of buffers, for the generated bindings.
+	types: HashMap<ShaderBufferHandle, BufferType>,
+}
+
+// The WGSL type of a buffer, along with the definitions of any structs it uses.
+#[derive(Clone)]
+pub(crate) struct BufferType {
+	pub wgsl_type: String,
+	pub structs: Vec<WgslStruct>,
 }
 
 /// This is an opaque identifier you can store to reference a buffer again in the future.
-#[derive(Clone, Copy, Eq, PartialEq, Hash)]
+#[derive(Clone, Copy, Eq, PartialEq, Hash, PartialOrd, Ord)]
 pub enum ShaderBufferHandle {
 	#[doc(hidden)]
 	Bound { group: u32, id: u32 },
@@ -345,6 +449,28 @@ impl Display for ShaderBufferHandle {
 	}
 }
 
+/// A problem with a compute sequence that means it can't run with the buffers in a [ShaderBufferSet], as found by [validate_sequence](ShaderBufferSet::validate_sequence).
+#[derive(Clone, Debug)]
+pub struct SequenceError {
+	/// The index of the task the problem is in, counting into loops the same way as [ComputeErrorEvent](crate::ComputeErrorEvent::task).
+	pub task: usize,
+
+	/// The index of the step within that task, if the problem is with a particular step rather than a condition.
+	pub step: Option<usize>,
+
+	/// A description of the problem.
+	pub error: String,
+}
+
+impl Display for SequenceError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self.step {
+			Some(step) => write!(f, "Task {}, step {}: {}", self.task, step, self.error),
+			None => write!(f, "Task {}: {}", self.task, self.error),
+		}
+	}
+}
+
 fn bind_group_layout(buffers: &Vec<&ShaderBufferInfo>, device: &RenderDevice) -> BindGroupLayout {
 	device.create_bind_group_layout(
 		None,
@@ -353,7 +479,9 @@ fn bind_group_layout(buffers: &Vec<&ShaderBufferInfo>, device: &RenderDevice) ->
 }
 
 impl ShaderBufferSet {
-	pub(crate) fn new() -> Self { Self { buffers: HashMap::new(), groups: Vec::new(), next_id: 0 } }
+	pub(crate) fn new() -> Self {
+		Self { buffers: HashMap::new(), groups: Vec::new(), next_id: 0, names: HashMap::new(), types: HashMap::new() }
+	}
 
 	/// Add a new uninitialized storage buffer.
 	/// - render_device: The [RenderDevice] resouce from Bevy.
@@ -396,6 +524,16 @@ impl ShaderBufferSet {
 		)
 	}
 
+	/// Add a new storage buffer to hold the workgroup counts for [RunShaderIndirect](crate::ComputeAction::RunShaderIndirect) steps. Each set of counts is three `u32`s, in the X, Y and Z dimensions, and the sets are packed one after another, 12 bytes apart. In a shader, this can be declared as an array of structs with three `u32` fields, or as a flat array of `u32`s.
+	/// - render_device: The [RenderDevice] resouce from Bevy.
+	/// - args: The initial workgroup counts. The buffer will be just big enough to hold these, so provide one entry for every set of counts you need.
+	/// - binding: How the buffer will be bound for access from the shader. See [Binding] for details. Specifying [Binding::Double] makes this a double buffer, in which case both buffers will be initialized with the provided counts.
+	pub fn add_indirect_args(
+		&mut self, render_device: &RenderDevice, args: &[[u32; 3]], binding: Binding,
+	) -> ShaderBufferHandle {
+		self.store_buffer(binding, ShaderBufferInfo::new_indirect_args(render_device, args, binding))
+	}
+
 	/// Add a new uniform buffer initialized with the provided data.
 	/// - render_device: The [RenderDevice] resouce from Bevy.
 	/// - render_queue: The [RenderQueue] resource from Bevy.
@@ -416,6 +554,7 @@ impl ShaderBufferSet {
 	/// - fill: One pixel's worth of data, provided as a byte array. The entire texture will be filled with this.
 	/// - access: Whether this texture is read-only, write-only or read-write. This is ignored if the texture is double buffered.
 	/// - binding: How the buffer will be bound for access from the shader. See [Binding] for details. Specifying [Binding::Double] makes this a double buffer, in which case the access mode specified in the previous argument is ignored.
+	#[allow(clippy::too_many_arguments)]
 	pub fn add_texture_fill(
 		&mut self, images: &mut Assets<Image>, width: u32, height: u32, format: TextureFormat, fill: &[u8],
 		access: StorageTextureAccess, binding: Binding,
@@ -425,20 +564,40 @@ impl ShaderBufferSet {
 	}
 
 	pub(crate) fn bind_groups(&self, device: &RenderDevice, gpu_images: &RenderAssets<GpuImage>) -> Vec<BindGroup> {
-		self
-			.groups
-			.iter()
-			.map(|buffer_ids| {
-				let buffers = buffer_ids.iter().map(|id| self.buffers.get(id).unwrap()).collect::<Vec<_>>();
-				device.create_bind_group(
-					None,
-					&bind_group_layout(&buffers, &device),
-					buffers.iter().flat_map(|buffer| buffer.bind_group_entries(gpu_images)).collect::<Vec<_>>().as_slice(),
-				)
+		self.swapped_bind_groups(&[], device, gpu_images)
+	}
+
+	// The bind groups as they'd be if the given double buffers were swapped, for swaps that happen part way through
+	// a frame.
+	pub(crate) fn swapped_bind_groups(
+		&self, swapped: &[ShaderBufferHandle], device: &RenderDevice, gpu_images: &RenderAssets<GpuImage>,
+	) -> Vec<BindGroup> {
+		(0..self.groups.len() as u32)
+			.map(|group| {
+				let buffers = self.group_buffers(group).map(|(_, buffer)| buffer).collect::<Vec<_>>();
+				let entries = self
+					.group_buffers(group)
+					.flat_map(|(handle, buffer)| buffer.bind_group_entries(swapped.contains(&handle), gpu_images))
+					.collect::<Vec<_>>();
+				device.create_bind_group(None, &bind_group_layout(&buffers, device), &entries)
 			})
 			.collect()
 	}
 
+	// What's bound in every place in every bind group if the given double buffers were swapped. Bind groups made for
+	// the same swaps can be reused for as long as this stays the same.
+	pub(crate) fn bound_ids(&self, swapped: &[ShaderBufferHandle], gpu_images: &RenderAssets<GpuImage>) -> Vec<BoundId> {
+		(0..self.groups.len() as u32)
+			.flat_map(|group| self.group_buffers(group))
+			.flat_map(|(handle, buffer)| buffer.bound_storage(swapped.contains(&handle)))
+			.map(|(_, storage)| storage.bound_id(gpu_images))
+			.collect()
+	}
+
+	fn group_buffers(&self, group: u32) -> impl Iterator<Item = (ShaderBufferHandle, &ShaderBufferInfo)> {
+		self.groups[group as usize].iter().map(move |id| (ShaderBufferHandle::Bound { group, id: *id }, &self.buffers[id]))
+	}
+
 	pub(crate) fn bind_group_layouts(&self, device: &RenderDevice) -> Vec<BindGroupLayout> {
 		self
 			.groups
@@ -469,6 +628,78 @@ impl ShaderBufferSet {
 		if let Some(mut buffer) = buffer {
 			buffer.delete(images);
 		}
+		self.names.retain(|_, named| *named != handle);
+		self.types.remove(&handle);
+	}
+
+	/// Give a buffer a name, so that a [ComputeSequenceAsset](crate::ComputeSequenceAsset) can refer to it, and so it's declared in the [generated bindings](ShaderBufferSet::wgsl_bindings). If another buffer already has this name, the name is moved to this one.
+	/// - handle: The handle to the buffer to name.
+	/// - name: The name to give it.
+	pub fn name_buffer(&mut self, handle: ShaderBufferHandle, name: impl Into<String>) {
+		self.names.insert(name.into(), handle);
+	}
+
+	/// Get the handle of the buffer with the given name, if there is one.
+	pub fn buffer_by_name(&self, name: &str) -> Option<ShaderBufferHandle> { self.names.get(name).copied() }
+
+	/// Set the WGSL type a storage or uniform buffer is declared with in the [generated bindings](ShaderBufferSet::wgsl_bindings), like `array<f32>` or `Params`. Textures don't need one, since their type comes from their format.
+	/// - handle: The handle to the buffer.
+	/// - wgsl_type: The WGSL type of the buffer's contents.
+	pub fn set_buffer_type(&mut self, handle: ShaderBufferHandle, wgsl_type: impl Into<String>) {
+		self.types.insert(handle, BufferType { wgsl_type: wgsl_type.into(), structs: Vec::new() });
+	}
+
+	/// Set the WGSL type of a storage or uniform buffer from the Rust type it holds, like `Vec<Particle>`, so the [generated bindings](ShaderBufferSet::wgsl_bindings) declare it with the matching WGSL type, along with the definitions of any structs it uses. See [WgslType] for the types that can be used. Panics if a struct's WGSL definition isn't laid out the same as the Rust type, if a struct has the same name as a different one used by this or another buffer's type, or if this is a uniform buffer and the type can't be used in one.
+	/// - handle: The handle to the buffer.
+	pub fn set_buffer_type_of<T: WgslType>(&mut self, handle: ShaderBufferHandle) {
+		let mut structs = Vec::new();
+		T::wgsl_structs(&mut structs);
+		// The generated bindings declare the structs of every buffer's type together, so they can't clash either.
+		let others = self.types.iter().filter(|(other, _)| **other != handle).flat_map(|(_, other)| other.structs.iter());
+		if let Err(e) = check_struct_layouts(&structs).and_then(|_| check_struct_names(others.chain(structs.iter()))) {
+			panic!("Tried to set the type of {} to {}, but {}", handle, T::wgsl_type(), e);
+		}
+		if let Some(
+			ShaderBufferInfo::SingleBound { storage: ShaderBufferStorage::Uniform(_), .. }
+			| ShaderBufferInfo::SingleUnbound { storage: ShaderBufferStorage::Uniform(_) }
+			| ShaderBufferInfo::Double { storage: (ShaderBufferStorage::Uniform(_), _), .. },
+		) = self.get_buffer(handle)
+		{
+			T::assert_uniform_compat();
+		}
+		self.types.insert(handle, BufferType { wgsl_type: T::wgsl_type(), structs });
+	}
+
+	// Every binding of every named buffer, in order of group and binding. The two buffers of a double buffer are
+	// declared separately, with the names suffixed with _front and _back.
+	pub(crate) fn named_bindings(&self) -> Vec<NamedBinding> {
+		let mut bindings = Vec::new();
+		for (name, handle) in self.names.iter() {
+			let ShaderBufferHandle::Bound { id, .. } = handle else {
+				continue;
+			};
+			let wgsl_type = self.types.get(handle).cloned();
+			let mut add = |name: String, group: u32, binding: u32, resource: BoundResource| {
+				bindings.push(NamedBinding { name, group, binding, resource, wgsl_type: wgsl_type.clone() })
+			};
+			match self.buffers.get(id) {
+				Some(ShaderBufferInfo::SingleBound { binding: (group, binding), storage }) => {
+					add(name.clone(), *group, *binding, storage.bound_resource(None))
+				}
+				Some(ShaderBufferInfo::Double { binding: (group, (front, back)), storage, .. }) => {
+					add(
+						format!("{}_front", name),
+						*group,
+						*front,
+						storage.0.bound_resource(Some(StorageTextureAccess::ReadOnly)),
+					);
+					add(format!("{}_back", name), *group, *back, storage.1.bound_resource(Some(StorageTextureAccess::WriteOnly)));
+				}
+				_ => {}
+			}
+		}
+		bindings.sort_by_key(|binding| (binding.group, binding.binding));
+		bindings
 	}
 
 	/// Get the image handle for a texture buffer. If the provided buffer isn't a texture buffer, it will just return `None`. If the provided buffer is a double buffer, it will return the image handle for the current front buffer.
@@ -480,6 +711,12 @@ impl ShaderBufferSet {
 		}
 	}
 
+	pub(crate) fn texture_size(
+		&self, handle: ShaderBufferHandle, gpu_images: &RenderAssets<GpuImage>,
+	) -> Option<Extent3d> {
+		gpu_images.get(&self.image_handle(handle)?).map(|image| image.size)
+	}
+
 	/// Get the GPU buffer, as a [bevy_render::render_resource::buffer], for a storage or uniform buffer. If the provided buffer isn't a storage or uniform buffer, it will just return `None`. If the provided buffer is a double buffer, it will return the GPU buffer for the current front buffer.
 	pub fn gpu_buffer(&self, handle: ShaderBufferHandle) -> Option<Buffer> {
 		if let Some(buffer) = self.get_buffer(handle) {
@@ -489,10 +726,264 @@ impl ShaderBufferSet {
 		}
 	}
 
+	// The GPU buffer on one side of a double buffer, or the only GPU buffer of any other buffer.
+	pub(crate) fn gpu_buffer_side(&self, handle: ShaderBufferHandle, side: BufferSide) -> Option<Buffer> {
+		match (self.get_buffer(handle)?, side) {
+			(ShaderBufferInfo::Double { storage: (storage1, storage2), front, .. }, BufferSide::Back) => match front {
+				FrontBuffer::First => storage2.gpu_buffer(),
+				FrontBuffer::Second => storage1.gpu_buffer(),
+			},
+			(buffer, _) => buffer.gpu_buffer(),
+		}
+	}
+
+	// Finds the buffer at a binding in one of the set's bind groups, and what it's bound as there.
+	pub(crate) fn bound_resource(&self, group: u32, binding: u32) -> Option<(ShaderBufferHandle, BoundResource)> {
+		for id in self.groups.get(group as usize)? {
+			let resource = match self.buffers.get(id)? {
+				ShaderBufferInfo::SingleBound { binding: (_, bound), storage } if *bound == binding => {
+					storage.bound_resource(None)
+				}
+				ShaderBufferInfo::Double { binding: (_, (front, _)), storage: (storage, _), .. } if *front == binding => {
+					storage.bound_resource(Some(StorageTextureAccess::ReadOnly))
+				}
+				ShaderBufferInfo::Double { binding: (_, (_, back)), storage: (_, storage), .. } if *back == binding => {
+					storage.bound_resource(Some(StorageTextureAccess::WriteOnly))
+				}
+				_ => continue,
+			};
+			return Some((ShaderBufferHandle::Bound { group, id: *id }, resource));
+		}
+		None
+	}
+
+	// Checks a buffer can be cleared or filled by a step, either all of it or just the range given.
+	pub(crate) fn validate_buffer_write(
+		&self, handle: ShaderBufferHandle, side: BufferSide, range: Option<Range<u64>>, verb: &str,
+	) -> Result<(), String> {
+		let Some(buffer) = self.get_buffer(handle) else {
+			return Err(format!("Tried to {} {}, which doesn't exist", verb, handle));
+		};
+		if side == BufferSide::Back && !matches!(buffer, ShaderBufferInfo::Double { .. }) {
+			return Err(format!("Tried to {} the back buffer of {}, which isn't a double buffer", verb, handle));
+		}
+		let Some(buffer) = buffer.gpu_buffer() else {
+			return Err(format!("Tried to {} {}, which isn't a storage or uniform buffer", verb, handle));
+		};
+		if !buffer.usage().contains(BufferUsages::COPY_DST) {
+			return Err(format!("Tried to {} {}, but it wasn't created with the COPY_DST usage", verb, handle));
+		}
+		let range = range.unwrap_or(0..buffer.size());
+		if !range.start.is_multiple_of(4) || !range.end.is_multiple_of(4) {
+			return Err(format!(
+				"Tried to {} bytes {:?} of {}, but both ends have to be multiples of 4",
+				verb, range, handle
+			));
+		}
+		if range.start >= range.end {
+			return Err(format!("Tried to {} bytes {:?} of {}, which is an empty range", verb, range, handle));
+		}
+		if range.end > buffer.size() {
+			return Err(format!("Tried to {} bytes {:?} of {}, which is past the end of the buffer", verb, range, handle));
+		}
+		Ok(())
+	}
+
+	pub(crate) fn validate_indirect_args(&self, handle: ShaderBufferHandle, offset: u64) -> Result<(), String> {
+		let Some(buffer) = self.gpu_buffer(handle) else {
+			return Err(format!(
+				"Tried to use {} for indirect dispatch arguments, but it doesn't exist or isn't a storage buffer",
+				handle
+			));
+		};
+		if !buffer.usage().contains(BufferUsages::INDIRECT | BufferUsages::COPY_SRC) {
+			return Err(format!(
+				"Tried to use {} for indirect dispatch arguments, but it wasn't created with INDIRECT and COPY_SRC usages",
+				handle
+			));
+		}
+		if !offset.is_multiple_of(4) {
+			return Err(format!(
+				"Tried to use {} for indirect dispatch arguments at offset {}, which isn't a multiple of 4",
+				handle, offset
+			));
+		}
+		if offset + INDIRECT_ARGS_SIZE > buffer.size() {
+			return Err(format!(
+				"Tried to use {} for indirect dispatch arguments at offset {}, which is past the end of the buffer",
+				handle, offset
+			));
+		}
+		Ok(())
+	}
+
+	/// Checks that the buffers a sequence's tasks use exist, and can be used the way the tasks use them, so that mistakes are caught before the sequence starts rather than part way through it. [CopyBuffer](crate::ComputeAction::CopyBuffer) steps and task conditions need single storage buffers, conditions' buffers have to be the [size](crate::ComputeCondition::size) their predicates read, [SwapBuffers](crate::ComputeAction::SwapBuffers) steps need double buffers, [PerTexel](DispatchSize::PerTexel) dispatches need textures, [PerElement](DispatchSize::PerElement) dispatches need storage or uniform buffers, indirect arguments need a buffer like the ones [add_indirect_args](ShaderBufferSet::add_indirect_args) makes, [ClearBuffer](crate::ComputeAction::ClearBuffer) and [FillBuffer](crate::ComputeAction::FillBuffer) steps need storage or uniform buffers they can copy into, and any bind groups a shader step names have to exist. Every problem that's found is returned, not just the first.
+	///
+	/// Each buffer can only be copied back to the CPU once per task, so a task can't have two [CopyBuffer](crate::ComputeAction::CopyBuffer) steps that copy the same buffer, or copy the buffer its condition reads, or the condition of a [loop](ComputeSequenceElement) that ends with it reads. Tasks are numbered the same way as in [ComputeErrorEvent](crate::ComputeErrorEvent::task), counting into loops.
+	///
+	/// This is run on every [StartComputeEvent](crate::StartComputeEvent), so it's only needed to check a sequence ahead of time.
+	pub fn validate_sequence(&self, elements: &[ComputeSequenceElement]) -> Result<(), Vec<SequenceError>> {
+		let mut tasks = Vec::new();
+		let mut loops = Vec::new();
+		flatten_elements(elements, &mut tasks, &mut loops);
+		let errors = self.sequence_errors(&tasks, &loops);
+		if errors.is_empty() {
+			Ok(())
+		} else {
+			Err(errors)
+		}
+	}
+
+	pub(crate) fn sequence_errors(&self, tasks: &[ComputeTask], loops: &[ComputeLoop]) -> Vec<SequenceError> {
+		let mut errors = Vec::new();
+		let missing_label = |condition: &ComputeCondition| {
+			condition
+				.next_task
+				.as_deref()
+				.filter(|label| !tasks.iter().any(|task| task.label.as_deref() == Some(*label)))
+				.map(|label| format!("moves on to the task labelled \"{}\", but there isn't one", label))
+		};
+		for (index, task) in tasks.iter().enumerate() {
+			if let Some(condition) = &task.condition {
+				if let Some(problem) = self.condition_problem(condition) {
+					let error = format!("The task's condition reads {}, which {}", condition.buffer, problem);
+					errors.push(SequenceError { task: index, step: None, error });
+				}
+				if let Some(problem) = missing_label(condition) {
+					errors.push(SequenceError { task: index, step: None, error: format!("The task's condition {}", problem) });
+				}
+			}
+			for (step, step_errors) in task.steps.iter().map(|step| self.step_errors(step)).enumerate() {
+				errors.extend(step_errors.into_iter().map(|error| SequenceError { task: index, step: Some(step), error }));
+			}
+
+			// Every buffer that's copied back to the CPU during a task gets its own staging buffers, so a task can only
+			// copy each buffer once, whether it's for a step or for a condition.
+			let loop_conditions = loops.iter().filter_map(|compute_loop| match &compute_loop.kind {
+				ComputeLoopKind::Until(condition) if compute_loop.end == index + 1 => Some(condition.buffer),
+				_ => None,
+			});
+			let conditions: Vec<ShaderBufferHandle> =
+				task.condition.iter().map(|condition| condition.buffer).chain(loop_conditions).collect();
+			let mut copied: Vec<ShaderBufferHandle> = Vec::new();
+			for (step, step_info) in task.steps.iter().enumerate() {
+				let ComputeAction::CopyBuffer { src } = step_info.action else {
+					continue;
+				};
+				if copied.contains(&src) {
+					let error = format!("Tried to copy {}, which an earlier step in the task already copies", src);
+					errors.push(SequenceError { task: index, step: Some(step), error });
+				} else if conditions.contains(&src) {
+					let error = format!("Tried to copy {}, which is already copied back for a condition that ends the task", src);
+					errors.push(SequenceError { task: index, step: Some(step), error });
+				}
+				copied.push(src);
+			}
+		}
+		// A loop's condition is checked when its last task ends, so that's the task any problem with it is reported in.
+		for compute_loop in loops {
+			if let ComputeLoopKind::Until(condition) = &compute_loop.kind {
+				if let Some(problem) = self.condition_problem(condition) {
+					let error =
+						format!("The condition of the loop ending with this task reads {}, which {}", condition.buffer, problem);
+					errors.push(SequenceError { task: compute_loop.end - 1, step: None, error });
+				}
+				if let Some(problem) = missing_label(condition) {
+					let error = format!("The condition of the loop ending with this task {}", problem);
+					errors.push(SequenceError { task: compute_loop.end - 1, step: None, error });
+				}
+			}
+		}
+		errors
+	}
+
+	pub(crate) fn step_errors(&self, step: &ComputeStep) -> Vec<String> {
+		let mut errors = Vec::new();
+		match &step.action {
+			ComputeAction::RunShader { dispatch, .. } => match *dispatch {
+				DispatchSize::Workgroups(..) => {}
+				DispatchSize::PerTexel(texture) => match self.get_buffer(texture) {
+					None => {
+						errors.push(format!("Tried to dispatch one invocation per texel of {}, which doesn't exist", texture))
+					}
+					Some(buffer) if buffer.image_handle().is_none() => errors
+						.push(format!("Tried to dispatch one invocation per texel of {}, which isn't a texture buffer", texture)),
+					_ => {}
+				},
+				DispatchSize::PerElement { buffer, .. } => match self.get_buffer(buffer) {
+					None => {
+						errors.push(format!("Tried to dispatch one invocation per element of {}, which doesn't exist", buffer))
+					}
+					Some(info) if info.gpu_buffer().is_none() => errors.push(format!(
+						"Tried to dispatch one invocation per element of {}, which isn't a storage or uniform buffer",
+						buffer
+					)),
+					_ => {}
+				},
+			},
+			ComputeAction::RunShaderIndirect { args, offset, .. } => {
+				errors.extend(self.validate_indirect_args(*args, *offset).err());
+			}
+			ComputeAction::CopyBuffer { src } => {
+				errors.extend(self.copy_problem(*src).map(|problem| format!("Tried to copy {}, which {}", src, problem)));
+			}
+			ComputeAction::SwapBuffers { buffer } => match self.get_buffer(*buffer) {
+				None => errors.push(format!("Tried to swap {}, which doesn't exist", buffer)),
+				Some(ShaderBufferInfo::Double { .. }) => {}
+				Some(_) => errors.push(format!("Tried to swap {}, which isn't a double buffer", buffer)),
+			},
+			ComputeAction::ClearBuffer { buffer, range, side } => {
+				errors.extend(self.validate_buffer_write(*buffer, *side, range.clone(), "clear").err());
+			}
+			ComputeAction::FillBuffer { buffer, side, .. } => {
+				errors.extend(self.validate_buffer_write(*buffer, *side, None, "fill").err());
+			}
+		}
+		for group in step.action.bind_groups().unwrap_or_default() {
+			if *group as usize >= self.groups.len() {
+				errors.push(format!("Tried to use bind group {}, which doesn't exist", group));
+			}
+		}
+		for (buffer, access) in
+			step.reads.iter().map(|buffer| (buffer, "reads")).chain(step.writes.iter().map(|buffer| (buffer, "writes")))
+		{
+			if self.get_buffer(*buffer).is_none() {
+				errors.push(format!("The step declares that it {} {}, which doesn't exist", access, buffer));
+			}
+		}
+		errors
+	}
+
+	// Why a buffer can't be copied back to the CPU, if it can't be.
+	fn copy_problem(&self, handle: ShaderBufferHandle) -> Option<&'static str> {
+		match self.get_buffer(handle) {
+			None => Some("doesn't exist"),
+			Some(ShaderBufferInfo::Double { .. }) => Some("is a double buffer"),
+			Some(
+				ShaderBufferInfo::SingleBound { storage: ShaderBufferStorage::Storage { .. }, .. }
+				| ShaderBufferInfo::SingleUnbound { storage: ShaderBufferStorage::Storage { .. } },
+			) => None,
+			Some(_) => Some("isn't a storage buffer"),
+		}
+	}
+
+	// Checks that a condition's buffer can be copied back, and is the size its predicate reads.
+	fn condition_problem(&self, condition: &ComputeCondition) -> Option<String> {
+		if let Some(problem) = self.copy_problem(condition.buffer) {
+			return Some(problem.to_owned());
+		}
+		let size = self.gpu_buffer(condition.buffer)?.size();
+		condition
+			.size
+			.filter(|expected| *expected != size)
+			.map(|expected| format!("is {} bytes, but the predicate reads {} bytes", size, expected))
+	}
+
 	pub(crate) fn swap_front_buffer(&mut self, handle: ShaderBufferHandle) {
-		let buffer = self.get_mut_buffer(handle);
-		let Some(buffer) = buffer else {
-			panic!("Attempted to set the front buffer of {}, but it doesn't exist", handle);
+		// The render world's swaps arrive a frame late, so the buffer may have been deleted since, and there's nothing
+		// left to swap.
+		let Some(buffer) = self.get_mut_buffer(handle) else {
+			return;
 		};
 		let ShaderBufferInfo::Double { front, .. } = buffer else {
 			panic!("Attempt to set the front buffer of {}, which isn't a double buffer", handle);
@@ -545,92 +1036,134 @@ impl ShaderBufferSet {
 
 fn extract_resources(mut commands: Commands, buffers: Extract<Option<Res<ShaderBufferSet>>>) {
 	if let Some(buffers) = &*buffers {
-		commands.insert_resource(ShaderBufferSet::extract_resource(&buffers));
+		commands.insert_resource(ShaderBufferSet::extract_resource(buffers));
 	}
 }
 
+// Data is copied back to the CPU without ever waiting on the GPU. Each copy goes into a staging buffer of its own,
+// which is mapped once the frame that made the copy has been submitted, and read on whichever later frame the mapping
+// finishes. Staging buffers are reused once they've been read.
+#[derive(Default)]
+struct Readback {
+	// Staging buffers that aren't holding a copy.
+	free: Vec<Buffer>,
+	// Copies that haven't been read yet, oldest first.
+	copies: VecDeque<StagedCopy>,
+}
+
+// Filled in when a staging buffer's mapping finishes.
+type MapResult = Arc<Mutex<Option<Result<(), BufferAsyncError>>>>;
+
+struct StagedCopy {
+	buffer: Buffer,
+	// Set once the buffer's been mapped.
+	mapped: Option<MapResult>,
+}
+
 #[derive(Resource)]
 pub(crate) struct ShaderBufferRenderSet {
-	copy_buffers: HashMap<ShaderBufferHandle, Buffer>,
+	// Readbacks are kept per sequence, so that sequences running side by side can copy the same buffer.
+	readbacks: HashMap<(ComputeSequenceId, ShaderBufferHandle), Readback>,
 }
 
 impl ShaderBufferRenderSet {
-	fn new() -> Self { Self { copy_buffers: HashMap::new() } }
+	pub(crate) fn new() -> Self { Self { readbacks: HashMap::new() } }
 
-	pub fn create_copy_buffer(&mut self, handle: ShaderBufferHandle, buffers: &ShaderBufferSet, device: &RenderDevice) {
-		if self.copy_buffers.contains_key(&handle) {
-			panic!("Tried to create a copy buffer for {}, which already has one", handle);
+	pub fn create_copy_buffer(
+		&mut self, sequence: ComputeSequenceId, handle: ShaderBufferHandle, buffers: &ShaderBufferSet,
+	) -> Result<(), String> {
+		if self.readbacks.contains_key(&(sequence, handle)) {
+			return Err(format!("Tried to copy {} back to the CPU twice at once", handle));
 		}
-		let Some(src) = buffers.get_buffer(handle) else {
-			panic!("Tried to create a copy buffer for {}, which does not exist", handle);
-		};
-		let storage = match &src {
-			ShaderBufferInfo::SingleBound { storage, .. } | ShaderBufferInfo::SingleUnbound { storage } => storage,
-			_ => panic!("Tried to create a copy buffer for {}, which is a double buffer", handle),
-		};
-		let ShaderBufferStorage::Storage { buffer: src, .. } = storage else {
-			panic!("Tried to create a copy buffer for {}, which is not a storage buffer", handle);
-		};
-		let dst = ShaderBufferInfo::new_storage_uninit(
-			device,
-			src.size() as u32,
-			BufferUsages::COPY_DST | BufferUsages::MAP_READ,
-			Binding::SingleUnbound,
-			false,
-		);
-		let ShaderBufferInfo::SingleUnbound { storage: dst_storage } = dst else {
-			panic!("Tried to create a copy buffer for {}, but somehow it ended up not unbound", handle);
-		};
-		let ShaderBufferStorage::Storage { buffer: dst, .. } = dst_storage else {
-			panic!("Tried to create a copy buffer for {}, but somehow it ended up as a non-storage buffer", handle);
-		};
-		self.copy_buffers.insert(handle, dst);
+		if let Some(problem) = buffers.copy_problem(handle) {
+			return Err(format!("Tried to copy {}, which {}", handle, problem));
+		}
+		self.readbacks.insert((sequence, handle), Readback::default());
+		Ok(())
 	}
 
-	pub fn remove_copy_buffer(&mut self, handle: ShaderBufferHandle) {
-		let Some(buffer) = self.copy_buffers.get(&handle) else {
-			panic!("Tried to remove copy buffer for {}, but it doesn't have one", handle);
+	pub fn remove_copy_buffer(&mut self, sequence: ComputeSequenceId, handle: ShaderBufferHandle) {
+		let Some(readback) = self.readbacks.remove(&(sequence, handle)) else {
+			return;
 		};
-		buffer.destroy();
-		self.copy_buffers.remove(&handle);
+		for buffer in readback.free.into_iter().chain(readback.copies.into_iter().map(|copy| copy.buffer)) {
+			buffer.destroy();
+		}
 	}
 
-	pub fn copy_to_copy_buffer(
-		&self, handle: ShaderBufferHandle, buffers: &ShaderBufferSet, context: &mut RenderContext,
-	) {
-		let Some(src) = buffers.get_buffer(handle) else {
-			panic!("Tried to copy from buffer {}, which doesn't exist", handle);
-		};
-		let src_storage = match &src {
-			ShaderBufferInfo::SingleBound { storage, .. } | ShaderBufferInfo::SingleUnbound { storage } => storage,
-			_ => panic!("Tried to copy from buffer {}, which is a double buffer", handle),
+	// Gets a staging buffer ready for a copy that's about to be recorded this frame.
+	pub fn stage_copy(
+		&mut self, sequence: ComputeSequenceId, handle: ShaderBufferHandle, buffers: &ShaderBufferSet,
+		device: &RenderDevice,
+	) -> Result<Buffer, String> {
+		let Some(readback) = self.readbacks.get_mut(&(sequence, handle)) else {
+			return Err(format!("Tried to copy {} back to the CPU without setting it up first", handle));
 		};
-		let ShaderBufferStorage::Storage { buffer: src, .. } = src_storage else {
-			panic!("Tried to copy from buffer {}, which is not a storage buffer", handle);
+		let Some(src) = buffers.gpu_buffer(handle) else {
+			return Err(format!("Tried to copy {}, which doesn't exist", handle));
 		};
-		let Some(dst) = self.copy_buffers.get(&handle) else {
-			panic!("Tried to copy {} to it's copy buffer, but it doesn't yet have one", handle);
-		};
-		let encoder = context.command_encoder();
-		encoder.copy_buffer_to_buffer(&src, 0, &dst, 0, src.size());
+		// If the buffer's been replaced with one of a different size, the old staging buffers are no use.
+		readback.free.retain(|buffer| buffer.size() == src.size());
+		let buffer = readback.free.pop().unwrap_or_else(|| {
+			device.create_buffer(&BufferDescriptor {
+				label: None,
+				size: src.size(),
+				usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+				mapped_at_creation: false,
+			})
+		});
+		readback.copies.push_back(StagedCopy { buffer: buffer.clone(), mapped: None });
+		Ok(buffer)
 	}
 
-	pub fn copy_from_copy_buffer_to_vec(&self, handle: ShaderBufferHandle, device: &RenderDevice) -> Vec<u8> {
-		if let Some(buffer) = self.copy_buffers.get(&handle) {
-			let buffer_slice = buffer.slice(..);
-			let (sender, receiver) = channel();
-			buffer_slice.map_async(MapMode::Read, move |result| {
-				sender.send(result).unwrap();
-			});
-			device.poll(Maintain::Wait);
-			receiver.recv().unwrap().unwrap();
-			let result = buffer_slice.get_mapped_range().to_vec();
-			buffer.unmap();
-			result
-		} else {
-			panic!("Tried to copy from buffer {} to vec when it has not yet been copied to a copy buffer", handle);
+	// Starts mapping every copy a sequence has staged. This has to wait until the frame they were recorded in has been
+	// submitted, so it's done at the start of the next one.
+	pub fn map_staged_copies(&mut self, sequence: ComputeSequenceId) {
+		for ((readback_sequence, _), readback) in self.readbacks.iter_mut() {
+			if *readback_sequence != sequence {
+				continue;
+			}
+			for copy in readback.copies.iter_mut().filter(|copy| copy.mapped.is_none()) {
+				let mapped = Arc::new(Mutex::new(None));
+				let result = mapped.clone();
+				copy.buffer.slice(..).map_async(MapMode::Read, move |mapping| {
+					*result.lock().unwrap() = Some(mapping);
+				});
+				copy.mapped = Some(mapped);
+			}
 		}
 	}
+
+	// Reads every copy of a buffer that's finished mapping, oldest first.
+	pub fn read_copies(
+		&mut self, sequence: ComputeSequenceId, handle: ShaderBufferHandle,
+	) -> Result<Vec<Vec<u8>>, String> {
+		let Some(readback) = self.readbacks.get_mut(&(sequence, handle)) else {
+			return Ok(Vec::new());
+		};
+		let mut data = Vec::new();
+		while let Some(mapping) =
+			readback.copies.front().and_then(|copy| copy.mapped.as_ref()).and_then(|mapped| mapped.lock().unwrap().take())
+		{
+			let copy = readback.copies.pop_front().unwrap();
+			if let Err(e) = mapping {
+				copy.buffer.destroy();
+				return Err(format!("Couldn't copy {} back to the CPU: {}", handle, e));
+			}
+			data.push(copy.buffer.slice(..).get_mapped_range().to_vec());
+			copy.buffer.unmap();
+			readback.free.push(copy.buffer);
+		}
+		Ok(data)
+	}
+
+	// Whether any of a sequence's copies are still on their way back from the GPU.
+	pub fn copies_pending(&self, sequence: ComputeSequenceId) -> bool {
+		self
+			.readbacks
+			.iter()
+			.any(|((readback_sequence, _), readback)| *readback_sequence == sequence && !readback.copies.is_empty())
+	}
 }
 
 pub(crate) struct ShaderBufferSetPlugin;
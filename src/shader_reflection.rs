@@ -0,0 +1,395 @@
+use std::sync::Arc;
+
+use bevy::{
+	prelude::*,
+	render::{
+		extract_resource::ExtractResource,
+		render_resource::{Shader, ShaderDefVal, ShaderImport, Source, StorageTextureAccess, TextureFormat},
+	},
+};
+use bevy_platform::collections::{hash_map::HashMap, HashSet};
+use naga::{
+	back::{
+		pipeline_constants::process_overrides,
+		wgsl::{write_string, WriterFlags},
+	},
+	front::wgsl::parse_str,
+	proc::Layouter,
+	valid::{Capabilities, ValidationFlags, Validator},
+	AddressSpace, ImageClass, StorageAccess, StorageFormat, TypeInner,
+};
+use naga_oil::compose::{preprocess::Preprocessor, Composer, NagaModuleDescriptor, ShaderDefValue};
+
+use super::{
+	compute_sequence::{ComputeSequences, ShaderVariant},
+	shader_buffer_set::{BoundResource, ShaderBufferSet},
+};
+
+// What's known about a shader variant once it's been parsed.
+#[derive(Clone)]
+pub(crate) struct ReflectedShader {
+	pub module: Arc<naga::Module>,
+	// Bevy's pipeline cache can't pass override constants to a pipeline, so variants with constants are compiled from
+	// a copy of the shader that has them already applied.
+	pub shader: Option<Handle<Shader>>,
+}
+
+// The parsed module for every shader variant used by a compute sequence. Shader assets only live in the main world,
+// so they're parsed here and the results are extracted for the compute nodes to use.
+#[derive(Resource, Clone, Default, ExtractResource)]
+pub(crate) struct ShaderReflections(pub HashMap<ShaderVariant, Result<ReflectedShader, String>>);
+
+impl ShaderReflections {
+	// Gets the parsed shader variant, or None if it hasn't been parsed yet.
+	pub fn get(&self, variant: &ShaderVariant) -> Option<Result<&ReflectedShader, String>> {
+		Some(self.0.get(variant)?.as_ref().map_err(Clone::clone))
+	}
+
+	// Gets the workgroup size declared on an entry point, or None if the shader hasn't been parsed yet.
+	pub fn workgroup_size(&self, variant: &ShaderVariant, entry_point: &str) -> Option<Result<[u32; 3], String>> {
+		let reflected = match self.get(variant)? {
+			Ok(reflected) => reflected,
+			Err(e) => return Some(Err(e)),
+		};
+		Some(
+			reflected
+				.module
+				.entry_points
+				.iter()
+				.find(|entry| entry.name == entry_point)
+				.map(|entry| entry.workgroup_size)
+				.ok_or_else(|| format!("{} has no entry point named {}", variant.shader, entry_point)),
+		)
+	}
+
+	// Describes every resource an entry point uses that doesn't match what the buffer set has bound in the same place,
+	// or None if the shader hasn't been parsed yet.
+	pub fn binding_mismatches(
+		&self, variant: &ShaderVariant, entry_point: &str, bind_groups: Option<&[u32]>, buffers: &ShaderBufferSet,
+	) -> Option<Result<Vec<String>, String>> {
+		let reflected = match self.get(variant)? {
+			Ok(reflected) => reflected,
+			Err(e) => return Some(Err(e)),
+		};
+		Some(binding_mismatches(&reflected.module, entry_point, bind_groups, buffers))
+	}
+}
+
+fn binding_mismatches(
+	module: &naga::Module, entry_point: &str, bind_groups: Option<&[u32]>, buffers: &ShaderBufferSet,
+) -> Result<Vec<String>, String> {
+	let Some(index) = module.entry_points.iter().position(|entry| entry.name == entry_point) else {
+		return Err(format!("The shader has no entry point named {}", entry_point));
+	};
+	// Only the resources the entry point actually uses have to match, so other entry points in the same file can use
+	// bindings of their own.
+	let info = Validator::new(ValidationFlags::all(), Capabilities::all())
+		.validate(module)
+		.map_err(|e| e.as_inner().to_string())?;
+	let mut layouter = Layouter::default();
+	layouter.update(module.to_ctx()).map_err(|e| e.to_string())?;
+
+	let mut mismatches = Vec::new();
+	for (handle, global) in module.global_variables.iter() {
+		let Some(binding) = &global.binding else {
+			continue;
+		};
+		if info.get_entry_point(index)[handle].is_empty() {
+			continue;
+		}
+		let name = format!(
+			"{} at @group({}) @binding({})",
+			global.name.as_deref().unwrap_or("An unnamed variable"),
+			binding.group,
+			binding.binding
+		);
+		let group = match bind_groups {
+			Some(groups) => match groups.get(binding.group as usize) {
+				Some(group) => *group,
+				None => {
+					mismatches.push(format!(
+						"{} is in a group the step doesn't bind, since it only binds {} groups",
+						name,
+						groups.len()
+					));
+					continue;
+				}
+			},
+			None => binding.group,
+		};
+		let Some((buffer, bound)) = buffers.bound_resource(group, binding.binding) else {
+			mismatches.push(format!("{} has no buffer at binding {} of group {}", name, binding.binding, group));
+			continue;
+		};
+
+		let size = layouter[global.ty].size as u64;
+		let too_small =
+			|bound_size: u64| format!("{} needs at least {} bytes, but {} is only {} bytes", name, size, buffer, bound_size);
+		match (global.space, &module.types[global.ty].inner, bound) {
+			(AddressSpace::Storage { access }, _, BoundResource::Storage { read_only, size: bound_size }) => {
+				if read_only && access.contains(StorageAccess::STORE) {
+					mismatches.push(format!("{} is read-write, but {} is read-only", name, buffer));
+				}
+				if bound_size < size {
+					mismatches.push(too_small(bound_size));
+				}
+			}
+			(AddressSpace::Uniform, _, BoundResource::Uniform { size: bound_size }) => {
+				if bound_size < size {
+					mismatches.push(too_small(bound_size));
+				}
+			}
+			(
+				AddressSpace::Handle,
+				TypeInner::Image { class: ImageClass::Storage { format, access }, .. },
+				BoundResource::StorageTexture { format: bound_format, access: bound_access },
+			) => {
+				if texture_format(*format) != bound_format {
+					mismatches.push(format!("{} is {:?}, but {} is {:?}", name, format, buffer, bound_format));
+				}
+				if texture_access(*access) != bound_access {
+					mismatches.push(format!(
+						"{} has {:?} access, but {} has {:?} access",
+						name,
+						texture_access(*access),
+						buffer,
+						bound_access
+					));
+				}
+			}
+			(space, inner, bound) => {
+				let kind = match (space, inner) {
+					(AddressSpace::Storage { .. }, _) => "a storage buffer",
+					(AddressSpace::Uniform, _) => "a uniform buffer",
+					(_, TypeInner::Image { class: ImageClass::Storage { .. }, .. }) => "a storage texture",
+					(_, TypeInner::Image { .. }) => "a sampled texture",
+					(_, TypeInner::Sampler { .. }) => "a sampler",
+					_ => "an unsupported kind of resource",
+				};
+				mismatches.push(format!("{} is {}, but {} is {}", name, kind, buffer, bound.kind()));
+			}
+		}
+	}
+	Ok(mismatches)
+}
+
+// The storage formats WGSL supports all have a texture format of the same name.
+fn texture_format(format: StorageFormat) -> TextureFormat {
+	match format {
+		StorageFormat::R8Unorm => TextureFormat::R8Unorm,
+		StorageFormat::R8Snorm => TextureFormat::R8Snorm,
+		StorageFormat::R8Uint => TextureFormat::R8Uint,
+		StorageFormat::R8Sint => TextureFormat::R8Sint,
+		StorageFormat::R16Uint => TextureFormat::R16Uint,
+		StorageFormat::R16Sint => TextureFormat::R16Sint,
+		StorageFormat::R16Float => TextureFormat::R16Float,
+		StorageFormat::Rg8Unorm => TextureFormat::Rg8Unorm,
+		StorageFormat::Rg8Snorm => TextureFormat::Rg8Snorm,
+		StorageFormat::Rg8Uint => TextureFormat::Rg8Uint,
+		StorageFormat::Rg8Sint => TextureFormat::Rg8Sint,
+		StorageFormat::R32Uint => TextureFormat::R32Uint,
+		StorageFormat::R32Sint => TextureFormat::R32Sint,
+		StorageFormat::R32Float => TextureFormat::R32Float,
+		StorageFormat::Rg16Uint => TextureFormat::Rg16Uint,
+		StorageFormat::Rg16Sint => TextureFormat::Rg16Sint,
+		StorageFormat::Rg16Float => TextureFormat::Rg16Float,
+		StorageFormat::Rgba8Unorm => TextureFormat::Rgba8Unorm,
+		StorageFormat::Rgba8Snorm => TextureFormat::Rgba8Snorm,
+		StorageFormat::Rgba8Uint => TextureFormat::Rgba8Uint,
+		StorageFormat::Rgba8Sint => TextureFormat::Rgba8Sint,
+		StorageFormat::Bgra8Unorm => TextureFormat::Bgra8Unorm,
+		StorageFormat::Rgb10a2Uint => TextureFormat::Rgb10a2Uint,
+		StorageFormat::Rgb10a2Unorm => TextureFormat::Rgb10a2Unorm,
+		StorageFormat::Rg11b10Ufloat => TextureFormat::Rg11b10Ufloat,
+		StorageFormat::R64Uint => TextureFormat::R64Uint,
+		StorageFormat::Rg32Uint => TextureFormat::Rg32Uint,
+		StorageFormat::Rg32Sint => TextureFormat::Rg32Sint,
+		StorageFormat::Rg32Float => TextureFormat::Rg32Float,
+		StorageFormat::Rgba16Uint => TextureFormat::Rgba16Uint,
+		StorageFormat::Rgba16Sint => TextureFormat::Rgba16Sint,
+		StorageFormat::Rgba16Float => TextureFormat::Rgba16Float,
+		StorageFormat::Rgba32Uint => TextureFormat::Rgba32Uint,
+		StorageFormat::Rgba32Sint => TextureFormat::Rgba32Sint,
+		StorageFormat::Rgba32Float => TextureFormat::Rgba32Float,
+		StorageFormat::R16Unorm => TextureFormat::R16Unorm,
+		StorageFormat::R16Snorm => TextureFormat::R16Snorm,
+		StorageFormat::Rg16Unorm => TextureFormat::Rg16Unorm,
+		StorageFormat::Rg16Snorm => TextureFormat::Rg16Snorm,
+		StorageFormat::Rgba16Unorm => TextureFormat::Rgba16Unorm,
+		StorageFormat::Rgba16Snorm => TextureFormat::Rgba16Snorm,
+	}
+}
+
+fn texture_access(access: StorageAccess) -> StorageTextureAccess {
+	if access.contains(StorageAccess::ATOMIC) {
+		StorageTextureAccess::Atomic
+	} else if access.contains(StorageAccess::LOAD | StorageAccess::STORE) {
+		StorageTextureAccess::ReadWrite
+	} else if access.contains(StorageAccess::STORE) {
+		StorageTextureAccess::WriteOnly
+	} else {
+		StorageTextureAccess::ReadOnly
+	}
+}
+
+// Adds a module a shader imports to the composer, after everything it imports in turn, the same way Bevy's pipeline
+// cache does, and notes down every shader asset that went into it. Returns None if any of them aren't loaded yet.
+fn add_import(
+	composer: &mut Composer, import: &ShaderImport, import_paths: &HashMap<ShaderImport, AssetId<Shader>>,
+	shaders: &Assets<Shader>, imported: &mut HashSet<AssetId<Shader>>,
+) -> Option<Result<(), String>> {
+	if composer.contains_module(&import.module_name()) {
+		return Some(Ok(()));
+	}
+	let id = *import_paths.get(import)?;
+	let shader = shaders.get(id)?;
+	imported.insert(id);
+	for import in shader.imports() {
+		if let Err(e) = add_import(composer, import, import_paths, shaders, imported)? {
+			return Some(Err(e));
+		}
+	}
+	Some(composer.add_composable_module(shader.into()).map(|_| ()).map_err(|e| e.emit_to_string(composer)))
+}
+
+// Parses a shader variant, or returns None if anything it imports isn't loaded yet. Every shader asset it imports is
+// added to imported, so it can be parsed again if any of them change.
+fn parse_shader(
+	shader: &Shader, variant: &ShaderVariant, shaders: &mut Assets<Shader>, imported: &mut HashSet<AssetId<Shader>>,
+) -> Option<Result<ReflectedShader, String>> {
+	let Source::Wgsl(source) = &shader.source else {
+		return Some(Err(format!("{} isn't a WGSL shader, so it can't be reflected", shader.path)));
+	};
+	let shader_defs = shader
+		.shader_defs
+		.iter()
+		.chain(variant.shader_defs.iter())
+		.map(|def| match def.clone() {
+			ShaderDefVal::Bool(key, value) => (key, ShaderDefValue::Bool(value)),
+			ShaderDefVal::Int(key, value) => (key, ShaderDefValue::Int(value)),
+			ShaderDefVal::UInt(key, value) => (key, ShaderDefValue::UInt(value)),
+		})
+		.collect::<std::collections::HashMap<_, _>>();
+	// Every loaded shader can be imported by its import path, whether that's its asset path or one it declares with
+	// #define_import_path, like the generated bindings.
+	let mut composer = Composer::non_validating();
+	let import_paths = shaders.iter().map(|(id, shader)| (shader.import_path().clone(), id)).collect::<HashMap<_, _>>();
+	for import in shader.imports() {
+		if let Err(e) = add_import(&mut composer, import, &import_paths, shaders, imported)? {
+			return Some(Err(e));
+		}
+	}
+	Some(compose_shader(composer, shader, source, variant, shader_defs, shaders))
+}
+
+// Composes a shader once everything it imports has been added to the composer, and applies any override constants.
+fn compose_shader(
+	mut composer: Composer, shader: &Shader, source: &str, variant: &ShaderVariant,
+	shader_defs: std::collections::HashMap<String, ShaderDefValue>, shaders: &mut Assets<Shader>,
+) -> Result<ReflectedShader, String> {
+	let module = composer
+		.make_naga_module(NagaModuleDescriptor {
+			source,
+			file_path: &shader.path,
+			shader_defs: shader_defs.clone(),
+			additional_imports: &shader.additional_imports,
+			..default()
+		})
+		.map_err(|e| e.emit_to_string(&composer))?;
+	if module.overrides.is_empty() && variant.constants.is_empty() {
+		return Ok(ReflectedShader { module: Arc::new(module), shader: None });
+	}
+
+	// naga_oil doesn't carry override constants through composition intact, so shaders that use them are parsed by
+	// naga directly, which means they can't have imports.
+	let preprocessed = Preprocessor::default().preprocess(source, &shader_defs).map_err(|e| e.to_string())?;
+	if !preprocessed.imports.is_empty() {
+		return Err(format!("{} uses override constants, so it can't have imports", shader.path));
+	}
+	let source = preprocessed.preprocessed_source;
+	let module = parse_str(&source).map_err(|e| e.emit_to_string(&source))?;
+
+	// Applying the constants also works out anything that depends on them, like the workgroup sizes.
+	for (name, _) in variant.constants.iter() {
+		if !module.overrides.iter().any(|(_, constant)| constant.name.as_deref() == Some(name)) {
+			return Err(format!("{} has no override constant named {}", shader.path, name));
+		}
+	}
+	let constants = variant.constants.iter().map(|(name, value)| (name.clone(), value.as_f64())).collect();
+	let info = Validator::new(ValidationFlags::all(), Capabilities::all())
+		.validate(&module)
+		.map_err(|e| e.emit_to_string(&source))?;
+	let (module, info) = process_overrides(&module, &info, &constants).map_err(|e| e.to_string())?;
+	let derived = if variant.constants.is_empty() {
+		None
+	} else {
+		let source = write_string(&module, &info, WriterFlags::empty()).map_err(|e| e.to_string())?;
+		Some(shaders.add(Shader::from_wgsl(source, format!("{} with constants {:?}", shader.path, variant.constants))))
+	};
+	Ok(ReflectedShader { module: Arc::new(module.into_owned()), shader: derived })
+}
+
+pub fn reflect_shaders(
+	mut reflections: ResMut<ShaderReflections>, sequences: Res<ComputeSequences>, asset_server: Res<AssetServer>,
+	mut shaders: ResMut<Assets<Shader>>, mut shader_events: EventReader<AssetEvent<Shader>>,
+	mut handles: Local<HashMap<String, Handle<Shader>>>,
+	mut imports: Local<HashMap<ShaderVariant, HashSet<AssetId<Shader>>>>,
+) {
+	// Hold on to a handle for every shader any sequence uses, so they're loaded and stay loaded.
+	let variants = sequences
+		.0
+		.values()
+		.flat_map(|sequence| sequence.tasks.iter().flat_map(|task| task.steps.iter()))
+		.filter_map(|step| step.action.shader_variant())
+		.collect::<HashSet<_>>();
+	for variant in variants.iter() {
+		if !handles.contains_key(&variant.shader) {
+			handles.insert(variant.shader.clone(), asset_server.load(&variant.shader));
+		}
+	}
+
+	// Shaders that have changed on disk need to be parsed again, and so does every shader that imports a module that's
+	// changed, like the generated bindings.
+	let mut stale = HashSet::new();
+	for event in shader_events.read() {
+		let (AssetEvent::Added { id } | AssetEvent::Modified { id }) = event else {
+			continue;
+		};
+		let modified = matches!(event, AssetEvent::Modified { .. });
+		stale.extend(
+			reflections
+				.0
+				.keys()
+				.filter(|variant| {
+					imports.get(*variant).is_some_and(|imported| imported.contains(id))
+						|| (modified && handles.get(&variant.shader).is_some_and(|handle| handle.id() == *id))
+				})
+				.cloned(),
+		);
+	}
+
+	// Variants that no running sequence uses any more are dropped, along with the handles of their shaders.
+	stale.extend(reflections.0.keys().filter(|variant| !variants.contains(*variant)).cloned());
+	for variant in stale {
+		imports.remove(&variant);
+		// The copy of the shader with the constants applied is only used through this variant, so it goes too. Compute
+		// nodes hold on to the pipeline they made from it until they have a new one.
+		if let Some(Ok(ReflectedShader { shader: Some(derived), .. })) = reflections.0.remove(&variant) {
+			shaders.remove(&derived);
+		}
+	}
+	handles.retain(|path, _| variants.iter().any(|variant| variant.shader == *path));
+
+	for variant in variants {
+		if reflections.0.contains_key(&variant) {
+			continue;
+		}
+		if let Some(shader) = shaders.get(&handles[&variant.shader]).cloned() {
+			let mut imported = HashSet::new();
+			if let Some(reflected) = parse_shader(&shader, &variant, &mut shaders, &mut imported) {
+				reflections.0.insert(variant.clone(), reflected);
+				imports.insert(variant, imported);
+			}
+		}
+	}
+}
@@ -0,0 +1,197 @@
+use super::compute_sequence::{ComputeAction, ComputeStepOrder, ComputeTask};
+use crate::shader_buffer_set::ShaderBufferHandle;
+
+// How a step uses a buffer, which decides which other steps it has to wait for.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Access {
+	Read,
+	Write,
+	Swap,
+}
+
+impl Access {
+	fn verb(self) -> &'static str {
+		match self {
+			Access::Read => "reads",
+			Access::Write => "writes",
+			Access::Swap => "swaps",
+		}
+	}
+}
+
+// Works out the order to run a task's steps in, as a list of batches. Every step in a batch only depends on steps in
+// earlier batches, so the shaders in a batch can all be dispatched in the same compute pass. Tasks that run their
+// steps in order just get a batch for each step.
+pub(crate) fn step_batches(task: &ComputeTask) -> Result<Vec<Vec<usize>>, String> {
+	if task.step_order == ComputeStepOrder::InOrder {
+		return Ok((0..task.steps.len()).map(|step| vec![step]).collect());
+	}
+
+	// Every buffer a step uses, including the ones its action uses without them being declared.
+	let accesses: Vec<Vec<(ShaderBufferHandle, Access)>> = task
+		.steps
+		.iter()
+		.map(|step| {
+			let mut accesses = Vec::new();
+			match step.action {
+				ComputeAction::CopyBuffer { src } => accesses.push((src, Access::Read)),
+				ComputeAction::RunShaderIndirect { args, .. } => accesses.push((args, Access::Read)),
+				ComputeAction::SwapBuffers { buffer } => accesses.push((buffer, Access::Swap)),
+				ComputeAction::ClearBuffer { buffer, .. } | ComputeAction::FillBuffer { buffer, .. } => {
+					accesses.push((buffer, Access::Write))
+				}
+				ComputeAction::RunShader { .. } => {}
+			}
+			accesses.extend(step.writes.iter().map(|buffer| (*buffer, Access::Write)));
+			accesses
+				.extend(step.reads.iter().filter(|buffer| !step.writes.contains(buffer)).map(|buffer| (*buffer, Access::Read)));
+			accesses
+		})
+		.collect();
+
+	// For each buffer, any two steps that use it, where at least one of them writes it, run in the order they were
+	// given, so a step that reads what an earlier step wrote sees it, and a step that writes what an earlier step read
+	// doesn't change it underneath that step. The steps that swap it run after all of them, again in the order they
+	// were given.
+	let mut edges = Vec::new();
+	for (to, to_accesses) in accesses.iter().enumerate() {
+		for &(buffer, to_access) in to_accesses {
+			for (from, from_accesses) in accesses.iter().enumerate() {
+				let Some(&(_, from_access)) = from_accesses.iter().find(|(handle, _)| *handle == buffer) else {
+					continue;
+				};
+				let before = match (from_access, to_access) {
+					(Access::Swap, Access::Swap) => from < to,
+					(_, Access::Swap) => from != to,
+					(Access::Swap, _) | (Access::Read, Access::Read) => false,
+					_ => from < to,
+				};
+				if before {
+					edges.push((from, to, buffer));
+				}
+			}
+		}
+	}
+
+	// The steps are sorted into batches, each of which holds the steps whose dependencies have all been run.
+	let mut dependencies = vec![0; task.steps.len()];
+	for &(_, to, _) in edges.iter() {
+		dependencies[to] += 1;
+	}
+	let mut batches = Vec::new();
+	let mut batch: Vec<usize> = (0..task.steps.len()).filter(|step| dependencies[*step] == 0).collect();
+	let mut sorted = 0;
+	while !batch.is_empty() {
+		sorted += batch.len();
+		let mut next_batch = Vec::new();
+		for &(from, to, _) in edges.iter() {
+			if batch.contains(&from) {
+				dependencies[to] -= 1;
+				if dependencies[to] == 0 {
+					next_batch.push(to);
+				}
+			}
+		}
+		next_batch.sort_unstable();
+		batches.push(batch);
+		batch = next_batch;
+	}
+	if sorted < task.steps.len() {
+		return Err(describe_cycle(&edges, &dependencies, &accesses));
+	}
+	Ok(batches)
+}
+
+// Finds a cycle among the steps that couldn't be sorted, and describes it.
+fn describe_cycle(
+	edges: &[(usize, usize, ShaderBufferHandle)], dependencies: &[usize], accesses: &[Vec<(ShaderBufferHandle, Access)>],
+) -> String {
+	// Every unsorted step depends on another unsorted step, so following those dependencies backwards from any of them
+	// has to come back round to a step that's already been visited.
+	let mut path: Vec<(usize, usize, ShaderBufferHandle)> = Vec::new();
+	let mut step = dependencies.iter().position(|count| *count > 0).unwrap();
+	let start = loop {
+		let edge = *edges.iter().find(|(from, to, _)| *to == step && dependencies[*from] > 0).unwrap();
+		path.push(edge);
+		step = edge.0;
+		if let Some(start) = path.iter().position(|(_, to, _)| *to == step) {
+			break start;
+		}
+	};
+	let links: Vec<String> = path[start..]
+		.iter()
+		.rev()
+		.map(|&(from, to, buffer)| {
+			let access = |step: usize| accesses[step].iter().find(|(handle, _)| *handle == buffer).unwrap().1;
+			let also = if access(from) == access(to) { "also " } else { "" };
+			format!("step {} {} {}, which step {} {}{}", from, access(from).verb(), buffer, to, also, access(to).verb())
+		})
+		.collect();
+	format!(
+		"The steps' buffer accesses depend on each other in a cycle, so they can't be ordered: {}",
+		links.join(", and ")
+	)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		compute_sequence::ComputeStep,
+		test_fixtures::{shader, A, B},
+	};
+
+	fn by_access(steps: impl IntoIterator<Item = ComputeStep>) -> ComputeTask {
+		steps.into_iter().fold(ComputeTask::new("Test").step_order(ComputeStepOrder::ByBufferAccess), ComputeTask::step)
+	}
+
+	#[test]
+	fn in_order_gets_a_batch_per_step() {
+		let task =
+			ComputeTask::new("Test").step(shader().writes([A])).step(shader().writes([B])).step(ComputeStep::copy(A));
+		assert_eq!(step_batches(&task).unwrap(), vec![vec![0], vec![1], vec![2]]);
+	}
+
+	#[test]
+	fn independent_steps_share_a_batch() {
+		let task = by_access([shader().writes([A]), shader().writes([B]), shader().reads([A, B])]);
+		assert_eq!(step_batches(&task).unwrap(), vec![vec![0, 1], vec![2]]);
+	}
+
+	#[test]
+	fn readers_share_a_batch() {
+		let task = by_access([ComputeStep::copy(A), shader().reads([A])]);
+		assert_eq!(step_batches(&task).unwrap(), vec![vec![0, 1]]);
+	}
+
+	#[test]
+	fn read_after_write_waits_for_the_write() {
+		let task = by_access([shader().writes([B]), shader().writes([A]), shader().reads([A])]);
+		assert_eq!(step_batches(&task).unwrap(), vec![vec![0, 1], vec![2]]);
+	}
+
+	#[test]
+	fn write_after_read_waits_for_the_read() {
+		// Consume, then reset.
+		let task = by_access([ComputeStep::copy(A), ComputeStep::clear(A)]);
+		assert_eq!(step_batches(&task).unwrap(), vec![vec![0], vec![1]]);
+		let task = by_access([shader().reads([A]), shader().writes([A]), shader().reads([A])]);
+		assert_eq!(step_batches(&task).unwrap(), vec![vec![0], vec![1], vec![2]]);
+	}
+
+	#[test]
+	fn swaps_run_after_everything_else_using_the_buffer() {
+		let task = by_access([ComputeStep::swap(A), shader().writes([A]), shader().reads([A]), ComputeStep::swap(A)]);
+		assert_eq!(step_batches(&task).unwrap(), vec![vec![1], vec![2], vec![0], vec![3]]);
+	}
+
+	#[test]
+	fn cycles_are_described() {
+		let task = by_access([ComputeStep::swap(A).writes([B]), shader().reads([A, B])]);
+		assert_eq!(
+			step_batches(&task).unwrap_err(),
+			"The steps' buffer accesses depend on each other in a cycle, so they can't be ordered: step 0 writes { id(2) }, \
+			 which step 1 reads, and step 1 reads { id(1) }, which step 0 swaps"
+		);
+	}
+}
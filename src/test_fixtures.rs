@@ -0,0 +1,14 @@
+// Buffers and steps shared by the unit tests. None of them touch the GPU, so the buffers don't have to exist.
+use crate::{
+	compute_sequence::{ComputeSequenceElement, ComputeStep, ComputeTask, DispatchSize},
+	shader_buffer_set::ShaderBufferHandle,
+};
+
+pub(crate) const A: ShaderBufferHandle = ShaderBufferHandle::Unbound { id: 1 };
+pub(crate) const B: ShaderBufferHandle = ShaderBufferHandle::Unbound { id: 2 };
+
+// A step that runs a shader, without reading or writing anything until it's told to.
+pub(crate) fn shader() -> ComputeStep { ComputeStep::run("shader.wgsl", "main", DispatchSize::Workgroups(1, 1, 1)) }
+
+// A task with a single shader step.
+pub(crate) fn task(label: &str) -> ComputeSequenceElement { ComputeTask::new(label).step(shader()).into() }
@@ -0,0 +1,140 @@
+use std::fmt::Write;
+
+use bevy::{
+	asset::weak_handle,
+	prelude::*,
+	render::render_resource::{Shader, StorageTextureAccess, TextureFormat},
+};
+
+use super::{
+	shader_buffer_set::{BoundResource, ShaderBufferSet},
+	wgsl_types::WgslStruct,
+};
+
+// The generated bindings are kept in a shader asset of their own, so that any shader can import them.
+pub(crate) const BINDINGS_SHADER_HANDLE: Handle<Shader> = weak_handle!("e3a9128c-368a-41ae-92ef-b3c740c45997");
+
+/// The import path of the module generated by [wgsl_bindings](ShaderBufferSet::wgsl_bindings).
+pub const BINDINGS_IMPORT_PATH: &str = "bevy_compute::bindings";
+
+impl ShaderBufferSet {
+	/// Generates a WGSL module declaring every named buffer in the set, with the group and binding numbers, access modes and texture formats it was added with. The plugin keeps a copy of this registered as a shader, so shaders can `#import` the declarations from [BINDINGS_IMPORT_PATH] rather than writing them out by hand. It's updated whenever the set changes, and the shaders that import it are recompiled.
+	///
+	/// Only buffers given a name with [name_buffer](ShaderBufferSet::name_buffer) are declared, with the name as the variable name. Double buffers are declared as two variables, with the name suffixed with `_front` and `_back`. Storage and uniform buffers also need a WGSL type, given with [set_buffer_type](ShaderBufferSet::set_buffer_type) or [set_buffer_type_of](ShaderBufferSet::set_buffer_type_of), since the set only knows their size. The definitions of any structs those types use come first. The group numbers are the set's own, so they only line up with the shader's for steps that don't pick their own [bind groups](crate::ComputeAction::RunShader::bind_groups).
+	pub fn wgsl_bindings(&self) -> String {
+		let mut source = format!("#define_import_path {}\n\n", BINDINGS_IMPORT_PATH);
+		source += "// Generated by bevy_compute from the ShaderBufferSet.\n\n";
+		let bindings = self.named_bindings();
+
+		// Every struct used by any of the buffers is defined once, before anything uses it.
+		let mut structs: Vec<&WgslStruct> = Vec::new();
+		for wgsl_struct in bindings.iter().flat_map(|binding| binding.wgsl_type.iter().flat_map(|ty| ty.structs.iter())) {
+			if !structs.iter().any(|existing| existing.name == wgsl_struct.name) {
+				structs.push(wgsl_struct);
+			}
+		}
+		for wgsl_struct in structs {
+			writeln!(source, "{}", wgsl_struct.definition).unwrap();
+		}
+
+		for binding in bindings.iter() {
+			let declaration = match (&binding.resource, &binding.wgsl_type) {
+				(BoundResource::StorageTexture { format, access }, _) => match wgsl_format(*format) {
+					Some(format) => {
+						format!("var {}: texture_storage_2d<{}, {}>", binding.name, format, wgsl_access(*access))
+					}
+					None => {
+						writeln!(source, "// {} is left out, since {:?} can't be used for storage textures.", binding.name, format)
+							.unwrap();
+						continue;
+					}
+				},
+				(BoundResource::Storage { read_only, .. }, Some(buffer_type)) => {
+					let access = if *read_only { "read" } else { "read_write" };
+					format!("var<storage, {}> {}: {}", access, binding.name, buffer_type.wgsl_type)
+				}
+				(BoundResource::Uniform { .. }, Some(buffer_type)) => {
+					format!("var<uniform> {}: {}", binding.name, buffer_type.wgsl_type)
+				}
+				(_, None) => {
+					writeln!(source, "// {} is left out, since it has no WGSL type.", binding.name).unwrap();
+					continue;
+				}
+			};
+			writeln!(source, "@group({}) @binding({}) {};", binding.group, binding.binding, declaration).unwrap();
+		}
+		source
+	}
+}
+
+fn wgsl_access(access: StorageTextureAccess) -> &'static str {
+	match access {
+		StorageTextureAccess::ReadOnly => "read",
+		StorageTextureAccess::WriteOnly => "write",
+		StorageTextureAccess::ReadWrite => "read_write",
+		StorageTextureAccess::Atomic => "atomic",
+	}
+}
+
+// The name WGSL gives each texture format that can be used for storage textures.
+fn wgsl_format(format: TextureFormat) -> Option<&'static str> {
+	Some(match format {
+		TextureFormat::R8Unorm => "r8unorm",
+		TextureFormat::R8Snorm => "r8snorm",
+		TextureFormat::R8Uint => "r8uint",
+		TextureFormat::R8Sint => "r8sint",
+		TextureFormat::R16Unorm => "r16unorm",
+		TextureFormat::R16Snorm => "r16snorm",
+		TextureFormat::R16Uint => "r16uint",
+		TextureFormat::R16Sint => "r16sint",
+		TextureFormat::R16Float => "r16float",
+		TextureFormat::Rg8Unorm => "rg8unorm",
+		TextureFormat::Rg8Snorm => "rg8snorm",
+		TextureFormat::Rg8Uint => "rg8uint",
+		TextureFormat::Rg8Sint => "rg8sint",
+		TextureFormat::R32Uint => "r32uint",
+		TextureFormat::R32Sint => "r32sint",
+		TextureFormat::R32Float => "r32float",
+		TextureFormat::Rg16Unorm => "rg16unorm",
+		TextureFormat::Rg16Snorm => "rg16snorm",
+		TextureFormat::Rg16Uint => "rg16uint",
+		TextureFormat::Rg16Sint => "rg16sint",
+		TextureFormat::Rg16Float => "rg16float",
+		TextureFormat::Rgba8Unorm => "rgba8unorm",
+		TextureFormat::Rgba8Snorm => "rgba8snorm",
+		TextureFormat::Rgba8Uint => "rgba8uint",
+		TextureFormat::Rgba8Sint => "rgba8sint",
+		TextureFormat::Rgb10a2Uint => "rgb10a2uint",
+		TextureFormat::Rgb10a2Unorm => "rgb10a2unorm",
+		TextureFormat::Rg11b10Ufloat => "rg11b10float",
+		TextureFormat::R64Uint => "r64uint",
+		TextureFormat::Rg32Uint => "rg32uint",
+		TextureFormat::Rg32Sint => "rg32sint",
+		TextureFormat::Rg32Float => "rg32float",
+		TextureFormat::Rgba16Unorm => "rgba16unorm",
+		TextureFormat::Rgba16Snorm => "rgba16snorm",
+		TextureFormat::Rgba16Uint => "rgba16uint",
+		TextureFormat::Rgba16Sint => "rgba16sint",
+		TextureFormat::Rgba16Float => "rgba16float",
+		TextureFormat::Rgba32Uint => "rgba32uint",
+		TextureFormat::Rgba32Sint => "rgba32sint",
+		TextureFormat::Rgba32Float => "rgba32float",
+		TextureFormat::Bgra8Unorm => "bgra8unorm",
+		_ => return None,
+	})
+}
+
+// Keeps the shader holding the generated bindings up to date with the buffer set. It's only replaced when the source
+// actually changes, since replacing it recompiles every shader that imports it.
+pub fn update_bindings_shader(
+	buffers: Res<ShaderBufferSet>, mut shaders: ResMut<Assets<Shader>>, mut source: Local<Option<String>>,
+) {
+	if !buffers.is_changed() {
+		return;
+	}
+	let bindings = buffers.wgsl_bindings();
+	if source.as_ref() != Some(&bindings) {
+		shaders.insert(&BINDINGS_SHADER_HANDLE, Shader::from_wgsl(bindings.clone(), "bevy_compute/bindings.wgsl"));
+		*source = Some(bindings);
+	}
+}
@@ -0,0 +1,345 @@
+use bevy::{
+	math::{IVec2, IVec3, IVec4, Mat2, Mat3, Mat4, UVec2, UVec3, UVec4, Vec2, Vec3, Vec4},
+	render::render_resource::{
+		encase::private::{ShaderSize, StructMetadata},
+		ShaderType,
+	},
+};
+use naga::{front::wgsl::parse_str, proc::Layouter, TypeInner};
+
+/// A Rust type with a WGSL equivalent, so a buffer holding it can be declared in the [generated bindings](crate::ShaderBufferSet::wgsl_bindings) with [set_buffer_type_of](crate::ShaderBufferSet::set_buffer_type_of). It's implemented for the scalar, vector and matrix types WGSL shares with Rust, for arrays and `Vec`s of other types that implement it, and for any struct declared with [wgsl_struct](crate::wgsl_struct).
+pub trait WgslType: ShaderType {
+	/// The name of the type in WGSL, like `f32`, `vec3<f32>` or `array<Particle>`.
+	fn wgsl_type() -> String;
+
+	/// Adds the definitions of any structs the type needs, including itself if it's a struct, to the list. Structs come after the ones they use, and ones already in the list aren't added again. A different struct with the same name as one in the list is still added, so the clash can be caught.
+	fn wgsl_structs(_structs: &mut Vec<WgslStruct>) {}
+}
+
+/// The WGSL definition of a struct made with [wgsl_struct](crate::wgsl_struct).
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct WgslStruct {
+	/// The name of the struct.
+	pub name: String,
+
+	/// The WGSL source defining the struct.
+	pub definition: String,
+
+	/// The size of the Rust type in bytes, as laid out by [ShaderType], which the WGSL definition has to match.
+	pub size: u64,
+
+	/// The offset of each field of the Rust type in bytes, as laid out by [ShaderType], which the WGSL definition has to match.
+	pub offsets: Vec<u64>,
+}
+
+impl WgslStruct {
+	#[doc(hidden)]
+	pub fn add<T: ShaderType>(structs: &mut Vec<WgslStruct>, name: &str, fields: &[(&str, String)])
+	where
+		T::ExtraMetadata: FieldOffsets,
+	{
+		let fields: String = fields.iter().map(|(field, wgsl_type)| format!("\t{}: {},\n", field, wgsl_type)).collect();
+		let wgsl_struct = WgslStruct {
+			name: name.to_owned(),
+			definition: format!("struct {} {{\n{}}}\n", name, fields),
+			size: T::min_size().get(),
+			offsets: T::METADATA.extra.field_offsets(),
+		};
+		// A different struct with the same name is still added, so that check_struct_layouts can report it.
+		if !structs.contains(&wgsl_struct) {
+			structs.push(wgsl_struct);
+		}
+	}
+}
+
+// Gets the field offsets out of the layout ShaderType works out for a struct.
+#[doc(hidden)]
+pub trait FieldOffsets {
+	fn field_offsets(&self) -> Vec<u64>;
+}
+
+impl<const N: usize> FieldOffsets for StructMetadata<N> {
+	fn field_offsets(&self) -> Vec<u64> { self.offsets.to_vec() }
+}
+
+/// Declares a struct along with its WGSL definition, by implementing [WgslType] for it. The struct is written as normal, and has to derive [ShaderType] as well. Every field has to be a type that implements [WgslType], and the WGSL struct gets the same fields in the same order, so it's laid out the same way [ShaderType] lays out the Rust one. Field attributes that change the layout, like `#[size(16)]` and `#[align(16)]`, aren't carried over, so structs that use them are caught by [set_buffer_type_of](crate::ShaderBufferSet::set_buffer_type_of). `#[size(runtime)]`, which a `Vec` as the last field needs, works as normal.
+///
+/// ```Rust
+/// wgsl_struct! {
+///     #[derive(ShaderType, Clone, Default)]
+///     struct Particle {
+///         position: Vec2,
+///         velocity: Vec2,
+///         age: f32,
+///     }
+/// }
+/// ```
+#[macro_export]
+macro_rules! wgsl_struct {
+	(
+		$(#[$attr:meta])*
+		$vis:vis struct $name:ident {
+			$($(#[$field_attr:meta])* $field_vis:vis $field:ident: $field_type:ty),* $(,)?
+		}
+	) => {
+		$(#[$attr])*
+		$vis struct $name {
+			$($(#[$field_attr])* $field_vis $field: $field_type),*
+		}
+
+		impl $crate::WgslType for $name {
+			fn wgsl_type() -> String { stringify!($name).to_owned() }
+
+			fn wgsl_structs(structs: &mut Vec<$crate::WgslStruct>) {
+				$(<$field_type as $crate::WgslType>::wgsl_structs(structs);)*
+				$crate::WgslStruct::add::<Self>(
+					structs,
+					stringify!($name),
+					&[$((stringify!($field), <$field_type as $crate::WgslType>::wgsl_type())),*],
+				);
+			}
+		}
+	};
+}
+
+macro_rules! wgsl_types {
+	($($rust_type:ty => $wgsl_type:literal),* $(,)?) => {
+		$(
+			impl WgslType for $rust_type {
+				fn wgsl_type() -> String { $wgsl_type.to_owned() }
+			}
+		)*
+	};
+}
+
+wgsl_types! {
+	f32 => "f32",
+	i32 => "i32",
+	u32 => "u32",
+	Vec2 => "vec2<f32>",
+	Vec3 => "vec3<f32>",
+	Vec4 => "vec4<f32>",
+	IVec2 => "vec2<i32>",
+	IVec3 => "vec3<i32>",
+	IVec4 => "vec4<i32>",
+	UVec2 => "vec2<u32>",
+	UVec3 => "vec3<u32>",
+	UVec4 => "vec4<u32>",
+	Mat2 => "mat2x2<f32>",
+	Mat3 => "mat3x3<f32>",
+	Mat4 => "mat4x4<f32>",
+}
+
+impl<T: WgslType + ShaderSize, const N: usize> WgslType for [T; N] {
+	fn wgsl_type() -> String { format!("array<{}, {}>", T::wgsl_type(), N) }
+
+	fn wgsl_structs(structs: &mut Vec<WgslStruct>) { T::wgsl_structs(structs) }
+}
+
+impl<T: WgslType + ShaderSize> WgslType for Vec<T> {
+	fn wgsl_type() -> String { format!("array<{}>", T::wgsl_type()) }
+
+	fn wgsl_structs(structs: &mut Vec<WgslStruct>) { T::wgsl_structs(structs) }
+}
+
+const LAYOUT_ATTRIBUTES: &str =
+	"Field attributes that change the layout, like #[size] and #[align], aren't carried over to WGSL";
+
+// Checks that no two different structs have the same name, since WGSL can only have one of them.
+pub(crate) fn check_struct_names<'a>(structs: impl IntoIterator<Item = &'a WgslStruct>) -> Result<(), String> {
+	let mut checked: Vec<&WgslStruct> = Vec::new();
+	for wgsl_struct in structs {
+		if checked.iter().any(|other| other.name == wgsl_struct.name && *other != wgsl_struct) {
+			return Err(format!(
+				"{} is the name of two different structs, which can't both be declared in WGSL",
+				wgsl_struct.name
+			));
+		}
+		checked.push(wgsl_struct);
+	}
+	Ok(())
+}
+
+// Checks that naga lays out each struct's WGSL definition with the same field offsets and size as ShaderType lays out
+// the Rust type.
+pub(crate) fn check_struct_layouts(structs: &[WgslStruct]) -> Result<(), String> {
+	check_struct_names(structs)?;
+	let source: String = structs.iter().map(|wgsl_struct| wgsl_struct.definition.as_str()).collect();
+	let module = parse_str(&source).map_err(|e| e.emit_to_string(&source))?;
+	let mut layouter = Layouter::default();
+	layouter.update(module.to_ctx()).map_err(|e| e.to_string())?;
+	for wgsl_struct in structs {
+		let Some((handle, ty)) = module.types.iter().find(|(_, ty)| ty.name.as_deref() == Some(&wgsl_struct.name)) else {
+			return Err(format!("Somehow the WGSL definition of {} didn't define it", wgsl_struct.name));
+		};
+		let TypeInner::Struct { members, .. } = &ty.inner else {
+			return Err(format!("Somehow the WGSL definition of {} isn't a struct", wgsl_struct.name));
+		};
+		for (member, offset) in members.iter().zip(wgsl_struct.offsets.iter()) {
+			if member.offset as u64 != *offset {
+				return Err(format!(
+					"The field {} of {} is at byte {} in WGSL, but byte {} in Rust. {}",
+					member.name.as_deref().unwrap_or_default(),
+					wgsl_struct.name,
+					member.offset,
+					offset,
+					LAYOUT_ATTRIBUTES
+				));
+			}
+		}
+		let size = layouter[handle].size as u64;
+		if size != wgsl_struct.size {
+			return Err(format!(
+				"{} is {} bytes in WGSL, but {} bytes in Rust. {}",
+				wgsl_struct.name, size, wgsl_struct.size, LAYOUT_ATTRIBUTES
+			));
+		}
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+// The structs are only ever laid out, never made, which the checks ShaderType generates for each field don't like.
+#[allow(dead_code)]
+mod tests {
+	use super::*;
+
+	wgsl_struct! {
+		#[derive(ShaderType)]
+		struct Body {
+			position: Vec3,
+			mass: f32,
+		}
+	}
+
+	wgsl_struct! {
+		#[derive(ShaderType)]
+		struct Padded {
+			flags: u32,
+			body: Body,
+			velocity: Vec3,
+		}
+	}
+
+	wgsl_struct! {
+		#[derive(ShaderType)]
+		struct Bodies {
+			count: u32,
+			#[size(runtime)]
+			bodies: Vec<Padded>,
+		}
+	}
+
+	wgsl_struct! {
+		#[derive(ShaderType)]
+		struct Resized {
+			#[size(16)]
+			value: f32,
+		}
+	}
+
+	wgsl_struct! {
+		#[derive(ShaderType)]
+		struct Shifted {
+			#[size(8)]
+			a: f32,
+			b: f32,
+			c: Vec4,
+		}
+	}
+
+	// Another struct with the same name as the one above, but different fields.
+	mod other {
+		use super::*;
+
+		wgsl_struct! {
+			#[derive(ShaderType)]
+			pub struct Body {
+				mass: f32,
+			}
+		}
+	}
+
+	wgsl_struct! {
+		#[derive(ShaderType)]
+		struct Clash {
+			first: Body,
+			second: other::Body,
+		}
+	}
+
+	fn structs<T: WgslType>() -> Vec<WgslStruct> {
+		let mut structs = Vec::new();
+		T::wgsl_structs(&mut structs);
+		structs
+	}
+
+	#[test]
+	fn structs_come_after_the_ones_they_use() {
+		let structs = structs::<Bodies>();
+		let names: Vec<&str> = structs.iter().map(|wgsl_struct| wgsl_struct.name.as_str()).collect();
+		assert_eq!(names, ["Body", "Padded", "Bodies"]);
+		assert_eq!(structs[1].definition, "struct Padded {\n\tflags: u32,\n\tbody: Body,\n\tvelocity: vec3<f32>,\n}\n");
+		assert_eq!(structs[2].definition, "struct Bodies {\n\tcount: u32,\n\tbodies: array<Padded>,\n}\n");
+		assert_eq!(<Vec<Padded>>::wgsl_type(), "array<Padded>");
+		assert_eq!(<[Body; 4]>::wgsl_type(), "array<Body, 4>");
+	}
+
+	#[test]
+	fn structs_are_only_added_once() {
+		let mut structs = structs::<Padded>();
+		Body::wgsl_structs(&mut structs);
+		Bodies::wgsl_structs(&mut structs);
+		assert_eq!(structs.len(), 3);
+	}
+
+	#[test]
+	fn padding_matches_naga() {
+		// A vec3 takes up 12 bytes but is aligned to 16, so an f32 after it fills the gap, and a struct holding one is
+		// aligned to 16 as well.
+		let structs = structs::<Bodies>();
+		let sizes: Vec<u64> = structs.iter().map(|wgsl_struct| wgsl_struct.size).collect();
+		assert_eq!(sizes, [16, 48, 64]);
+		let offsets: Vec<&[u64]> = structs.iter().map(|wgsl_struct| wgsl_struct.offsets.as_slice()).collect();
+		assert_eq!(offsets, [&[0, 12][..], &[0, 16, 32], &[0, 16]]);
+		assert_eq!(check_struct_layouts(&structs), Ok(()));
+	}
+
+	#[test]
+	fn layout_attributes_are_caught() {
+		assert_eq!(
+			check_struct_layouts(&structs::<Resized>()),
+			Err(
+				"Resized is 4 bytes in WGSL, but 16 bytes in Rust. Field attributes that change the layout, like #[size] \
+			     and #[align], aren't carried over to WGSL"
+					.to_owned()
+			)
+		);
+	}
+
+	#[test]
+	fn field_offsets_are_checked() {
+		// Both are 32 bytes, but the second field comes 4 bytes later in Rust.
+		assert_eq!(structs::<Shifted>()[0].size, 32);
+		assert_eq!(
+			check_struct_layouts(&structs::<Shifted>()),
+			Err(
+				"The field b of Shifted is at byte 4 in WGSL, but byte 8 in Rust. Field attributes that change the layout, \
+				 like #[size] and #[align], aren't carried over to WGSL"
+					.to_owned()
+			)
+		);
+	}
+
+	#[test]
+	fn different_structs_with_the_same_name_are_caught() {
+		let clash = structs::<Clash>();
+		assert_eq!(clash.len(), 3);
+		assert_eq!(
+			check_struct_layouts(&clash),
+			Err("Body is the name of two different structs, which can't both be declared in WGSL".to_owned())
+		);
+		// The same struct used by two types isn't a clash.
+		assert_eq!(check_struct_names(structs::<Body>().iter().chain(structs::<Padded>().iter())), Ok(()));
+	}
+}